@@ -1,13 +1,20 @@
+use runtime::module_definition::ModuleDef;
+
 use crate::{ast, lowering, parser, result::AssemblerResult};
 
-pub(crate) fn do_assemble(src: &str) -> AssemblerResult<Vec<u8>> {
+// source names the input file in the module's debug info
+pub fn assemble_module(src: &str, source: Option<&str>) -> AssemblerResult<ModuleDef> {
     let parse_result = parser::derive_parse_tree(src)?;
     let ast = ast::parse_tree_to_ast(parse_result)?;
-    let mdef = lowering::lower_ast(ast);
-    match bincode::serialize(&mdef) {
-        Ok(bytes) => Ok(bytes),
-        Err(err) => Err(crate::result::AssemblerError::SerializationError(format!(
-            "{err}"
-        ))),
+    let mut mdef = lowering::lower_ast(ast)?;
+    if let Some(source) = source {
+        mdef.set_source(source);
     }
+    Ok(mdef)
+}
+
+pub fn do_assemble(src: &str, source: Option<&str>) -> AssemblerResult<Vec<u8>> {
+    let mdef = assemble_module(src, source)?;
+    mdef.to_bytes()
+        .map_err(crate::result::AssemblerError::SerializationError)
 }
@@ -6,6 +6,7 @@ use super::instructions::Instruction;
 pub struct Block {
     pub name: String,
     pub body: Vec<Instruction>,
+    pub lines: Vec<u32>,
 }
 
 impl Block {
@@ -18,14 +19,17 @@ impl Block {
         let mut ret = Self {
             name: name.as_str().to_owned(),
             body: vec![],
+            lines: vec![],
         };
 
         for bi in f {
             match bi.as_rule() {
                 Rule::label => {}
                 Rule::statement => {
+                    let line = bi.line_col().0 as u32;
                     let i = Instruction::from_parse_tree(bi.into_inner().last().unwrap())?;
                     ret.body.push(i);
+                    ret.lines.push(line);
                 }
                 _ => panic!("unexpected entry {bi}"),
             }
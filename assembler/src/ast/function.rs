@@ -1,27 +1,47 @@
-use crate::{parser::Rule, result::AssemblerResult};
+use runtime::module_definition::Visibility;
+
+use crate::{
+    ast::{parse_integer_value, parse_visibility},
+    parser::Rule,
+    result::{AssemblerError, AssemblerResult},
+};
 
 use super::block::Block;
 
 #[derive(Debug)]
 pub struct Function {
     pub(crate) name: String,
+    pub(crate) visibility: Visibility,
+    pub(crate) arity: Option<usize>,
     pub(crate) body: Vec<Block>,
 }
 
 impl Function {
     pub(crate) fn from_parse_tree(p: pest::iterators::Pair<'_, Rule>) -> AssemblerResult<Self> {
         assert!(p.as_rule() == Rule::function);
+        let visibility = parse_visibility(&p);
         let f = p.into_inner();
         let name = f.find_first_tagged("name").expect("need a name");
+        let arity = match f.find_first_tagged("arity") {
+            Some(arity) => {
+                let count = arity.into_inner().next().expect("need a count");
+                Some(parse_integer_value(count.as_str()).map_err(|err| {
+                    AssemblerError::AstGenerationError(format!("invalid arity: {err}"))
+                })? as usize)
+            }
+            None => None,
+        };
 
         let mut ret = Self {
             name: name.as_str().to_owned(),
+            visibility,
+            arity,
             body: vec![],
         };
 
         for bb in f {
             match bb.as_rule() {
-                Rule::ident => {}
+                Rule::ident | Rule::visibility | Rule::arity => {}
                 Rule::block => {
                     let b = Block::from_parse_tree(bb)?;
                     ret.body.push(b);
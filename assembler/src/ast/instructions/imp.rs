@@ -64,6 +64,9 @@ impl super::Instruction {
         if matches!(rule, Rule::stmt_TOSLOT) {
             return super::toslot::from_parse_tree(p);
         }
+        if matches!(rule, Rule::stmt_TAILCALL) {
+            return super::tailcall::from_parse_tree(p);
+        }
         Err(crate::result::AssemblerError::AstGenerationError(format!(
             "invalid rule does not match an instruction {:?}",
             rule
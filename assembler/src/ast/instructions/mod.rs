@@ -7,6 +7,7 @@ mod jtrue;
 mod jump;
 mod lpush;
 mod push;
+mod tailcall;
 mod toslot;
 use either::Either;
 use runtime::intern_value::InternValue;
@@ -51,4 +52,5 @@ pub enum Instruction {
     FCALL(String),
     FROMSLOT(u16),
     TOSLOT(u16),
+    TAILCALL(String),
 }
@@ -1,4 +1,5 @@
 use pest::iterators::Pair;
+use runtime::module_definition::Visibility;
 
 use crate::{parser::Rule, result::AssemblerResult};
 
@@ -18,6 +19,14 @@ fn parse_string_trim(s: &str) -> String {
     value
 }
 
+fn parse_visibility(p: &Pair<'_, Rule>) -> Visibility {
+    if p.clone().into_inner().find_first_tagged("vis").is_some() {
+        Visibility::Public
+    } else {
+        Visibility::Private
+    }
+}
+
 pub fn parse_tree_to_ast(input: Pair<'_, Rule>) -> AssemblerResult<Module> {
     Module::from_parse_tree(input)
 }
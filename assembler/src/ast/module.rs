@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use pest::iterators::Pair;
 use runtime::intern_value::InternValue;
@@ -6,7 +6,7 @@ use runtime::intern_value::InternValue;
 use crate::{
     ast::{attribute::Attribute, types::TypeAlias},
     parser::Rule,
-    result::AssemblerResult,
+    result::{AssemblerError, AssemblerResult},
 };
 
 use super::{constant::Constant, function::Function, types::ValueType};
@@ -19,6 +19,7 @@ pub struct Module {
     pub(crate) functions: Vec<Function>,
     pub(crate) attributes: HashMap<String, String>,
     pub(crate) types: HashMap<String, ValueType>,
+    pub(crate) exported_types: HashSet<String>,
 }
 
 impl Module {
@@ -32,6 +33,7 @@ impl Module {
             functions: vec![],
             attributes: Default::default(),
             types: Default::default(),
+            exported_types: Default::default(),
         };
 
         ret.types.insert(
@@ -79,12 +81,11 @@ impl Module {
 }
 
 impl Module {
-    pub(crate) fn constant_idx_by_name(&self, name: &str) -> usize {
-        #[allow(clippy::expect_fun_call)]
-        *self
-            .constant_names
+    pub(crate) fn constant_idx_by_name(&self, name: &str) -> AssemblerResult<usize> {
+        self.constant_names
             .get(name)
-            .expect(&format!("invalid const name {}", name))
+            .copied()
+            .ok_or_else(|| AssemblerError::LoweringError(format!("invalid const name {name}")))
     }
 
     pub(crate) fn add_constant(&mut self, c: Constant) -> InternValue {
@@ -99,5 +100,8 @@ impl Module {
         let name = t.name();
         let dest = t.target();
         self.types.insert(name.to_owned(), dest.clone());
+        if t.visibility().is_public() {
+            self.exported_types.insert(name.to_owned());
+        }
     }
 }
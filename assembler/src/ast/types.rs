@@ -1,6 +1,12 @@
 use std::collections::HashMap;
 
-use crate::{ast::parse_string_trim, parser::Rule, result::AssemblerResult};
+use runtime::module_definition::Visibility;
+
+use crate::{
+    ast::{parse_string_trim, parse_visibility},
+    parser::Rule,
+    result::AssemblerResult,
+};
 
 #[derive(Clone, Debug)]
 pub(crate) enum ValueType {
@@ -123,6 +129,7 @@ impl RecordType {
 pub(crate) struct TypeAlias {
     name: String,
     vt: ValueType,
+    visibility: Visibility,
 }
 
 impl TypeAlias {
@@ -133,6 +140,10 @@ impl TypeAlias {
     pub fn target(&self) -> &ValueType {
         &self.vt
     }
+
+    pub fn visibility(&self) -> Visibility {
+        self.visibility
+    }
 }
 
 impl TypeAlias {
@@ -141,11 +152,16 @@ impl TypeAlias {
         type_map: &HashMap<String, ValueType>,
     ) -> AssemblerResult<Self> {
         assert!(p.as_rule() == Rule::typedef);
+        let visibility = parse_visibility(&p);
         let f = p.into_inner();
         let name = f.find_first_tagged("name").expect("need a name").as_str();
         let name = parse_string_trim(name);
         let value = f.find_first_tagged("value").expect("need a value");
         let vt = ValueType::from_parse_tree(value, type_map)?;
-        Ok(Self { name, vt })
+        Ok(Self {
+            name,
+            vt,
+            visibility,
+        })
     }
 }
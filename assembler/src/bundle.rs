@@ -0,0 +1,59 @@
+use runtime::{bundle::Bundle, module_definition::ModuleDef};
+
+use crate::{
+    assembler::assemble_module,
+    result::{AssemblerError, AssemblerResult},
+};
+
+// assembles .tas files and deserializes anything else as a module file
+pub fn read_module_file(path: &str) -> AssemblerResult<ModuleDef> {
+    if path.ends_with(".tas") {
+        let src = std::fs::read_to_string(path)
+            .map_err(|err| AssemblerError::InputError(format!("{path}: {err}")))?;
+        assemble_module(&src, Some(path))
+    } else {
+        let bytes = std::fs::read(path)
+            .map_err(|err| AssemblerError::InputError(format!("{path}: {err}")))?;
+        ModuleDef::from_bytes(&bytes)
+            .map_err(|err| AssemblerError::InputError(format!("{path}: {err}")))
+    }
+}
+
+// without an explicit entry the bundle starts from main in the last module,
+// if there is one
+pub fn make_bundle(modules: Vec<ModuleDef>, entry: Option<&str>) -> AssemblerResult<Bundle> {
+    let default_entry = modules
+        .last()
+        .filter(|m| m.functions().any(|f| f.name() == "main"))
+        .map(|m| format!("{}.main", m.name()));
+    let mut bundle = Bundle::new(None);
+    for mdef in modules {
+        if bundle.modules().any(|m| m.name() == mdef.name()) {
+            return Err(AssemblerError::InputError(format!(
+                "module {} appears more than once",
+                mdef.name()
+            )));
+        }
+        bundle.add_module(mdef);
+    }
+    match entry {
+        Some(entry) if !bundle.defines_function(entry) => {
+            return Err(AssemblerError::InputError(format!(
+                "entry function {entry} is not defined in the bundle"
+            )));
+        }
+        Some(entry) => bundle.set_entry(Some(entry)),
+        None => bundle.set_entry(default_entry.as_deref()),
+    }
+    Ok(bundle)
+}
+
+pub fn do_bundle(inputs: &[String], entry: Option<&str>) -> AssemblerResult<Vec<u8>> {
+    let modules = inputs
+        .iter()
+        .map(|input| read_module_file(input))
+        .collect::<AssemblerResult<Vec<ModuleDef>>>()?;
+    make_bundle(modules, entry)?
+        .to_bytes()
+        .map_err(AssemblerError::SerializationError)
+}
@@ -0,0 +1,9 @@
+pub mod assembler;
+pub mod ast;
+pub mod bundle;
+pub mod lowering;
+pub mod parser;
+pub mod result;
+
+#[cfg(test)]
+pub mod test;
@@ -1,19 +1,19 @@
 use runtime::{instruction_def::InstructionDef, module_definition::ModuleDef};
 
-use crate::ast::{instructions::Instruction, module::Module};
+use crate::{
+    ast::{instructions::Instruction, module::Module},
+    result::AssemblerResult,
+};
 
 pub(crate) fn lower_instruction(
-    ast: &Module,
-    _mdef: &mut ModuleDef,
+    _ast: &Module,
+    mdef: &mut ModuleDef,
     input: &Instruction,
     _b: &mut runtime::builder::Builder,
-) -> Vec<InstructionDef> {
+) -> AssemblerResult<Vec<InstructionDef>> {
     if let Instruction::FCALL(tgt) = input {
-        let idx = ast.constant_idx_by_name(tgt);
-        let push = InstructionDef::PUSH(idx as u16);
-        let flookup = InstructionDef::FLOOKUP;
-        let call = InstructionDef::CALL;
-        vec![push, flookup, call]
+        let idx = mdef.add_call_target(tgt);
+        Ok(vec![InstructionDef::CALLDIRECT(idx as u16)])
     } else {
         panic!("invalid lowering");
     }
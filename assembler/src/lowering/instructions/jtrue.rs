@@ -1,16 +1,21 @@
 use runtime::{instruction_def::InstructionDef, module_definition::ModuleDef};
 
-use crate::ast::{instructions::Instruction, module::Module};
+use crate::{
+    ast::{instructions::Instruction, module::Module},
+    result::{AssemblerError, AssemblerResult},
+};
 
 pub(crate) fn lower_instruction(
     _ast: &Module,
     _mdef: &mut ModuleDef,
     input: &Instruction,
     b: &mut runtime::builder::Builder,
-) -> Vec<InstructionDef> {
+) -> AssemblerResult<Vec<InstructionDef>> {
     if let Instruction::JTRUE(tgt) = input {
-        let tgt = b.find_block(tgt).expect("missing target label");
-        vec![InstructionDef::JTRUE(tgt)]
+        let tgt = b
+            .find_block(tgt)
+            .ok_or_else(|| AssemblerError::LoweringError(format!("missing target label {tgt}")))?;
+        Ok(vec![InstructionDef::JTRUE(tgt)])
     } else {
         panic!("invalid lowering");
     }
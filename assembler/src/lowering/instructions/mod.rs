@@ -6,13 +6,17 @@ mod jtrue;
 mod jump;
 mod lpush;
 mod push;
+mod tailcall;
 mod toslot;
-use crate::ast::{instructions::Instruction, module::Module};
+use crate::{
+    ast::{instructions::Instruction, module::Module},
+    result::AssemblerResult,
+};
 use runtime::{instruction_def::InstructionDef, module_definition::ModuleDef};
 macro_rules! trivial_lowering {
     ($input:expr, $candidate:ident) => {
         if matches!($input, Instruction::$candidate) {
-            return vec![InstructionDef::$candidate];
+            return Ok(vec![InstructionDef::$candidate]);
         }
     };
 }
@@ -21,7 +25,7 @@ pub(crate) fn lower_instruction(
     mdef: &mut ModuleDef,
     input: &Instruction,
     b: &mut runtime::builder::Builder,
-) -> Vec<InstructionDef> {
+) -> AssemblerResult<Vec<InstructionDef>> {
     trivial_lowering!(input, NOP);
     trivial_lowering!(input, ADD);
     trivial_lowering!(input, SUB);
@@ -75,6 +79,9 @@ pub(crate) fn lower_instruction(
     if let Instruction::TOSLOT(_) = input {
         return toslot::lower_instruction(ast, mdef, input, b);
     }
+    if let Instruction::TAILCALL(_) = input {
+        return tailcall::lower_instruction(ast, mdef, input, b);
+    }
     panic!(
         "instruction {:?} should have been handled but is not",
         input
@@ -0,0 +1,20 @@
+use runtime::{instruction_def::InstructionDef, module_definition::ModuleDef};
+
+use crate::{
+    ast::{instructions::Instruction, module::Module},
+    result::AssemblerResult,
+};
+
+pub(crate) fn lower_instruction(
+    _ast: &Module,
+    mdef: &mut ModuleDef,
+    input: &Instruction,
+    _b: &mut runtime::builder::Builder,
+) -> AssemblerResult<Vec<InstructionDef>> {
+    if let Instruction::TAILCALL(tgt) = input {
+        let idx = mdef.add_call_target(tgt);
+        Ok(vec![InstructionDef::TAILCALL(idx as u16)])
+    } else {
+        panic!("invalid lowering");
+    }
+}
@@ -3,12 +3,13 @@ mod instructions;
 use runtime::{
     builder::{BasicBlock, Builder},
     intern_value::InternValue,
-    module_definition::{FunctionDef, ModuleDef},
+    module_definition::{FunctionDef, ModuleDef, Visibility},
     types::{array::ArrayType, record::RecordType, typedef::TypeDef, RuntimeType},
 };
 
-use crate::ast::{
-    block::Block, constant::Constant, function::Function, module::Module, types::ValueType,
+use crate::{
+    ast::{block::Block, constant::Constant, function::Function, module::Module, types::ValueType},
+    result::AssemblerResult,
 };
 
 use self::instructions::lower_instruction;
@@ -49,20 +50,24 @@ fn lower_basic_block(
     mdef: &mut ModuleDef,
     input: &Block,
     b: &mut Builder,
-) -> BasicBlock {
+) -> AssemblerResult<BasicBlock> {
     let mut ret = b.find_block(&input.name).expect("invalid block");
 
-    for i in &input.body {
-        let lis = lower_instruction(ast, mdef, i, b);
+    for (i, line) in std::iter::zip(&input.body, &input.lines) {
+        let lis = lower_instruction(ast, mdef, i, b)?;
         for li in lis {
-            ret.append_instruction(li);
+            ret.append_instruction_at_line(li, *line);
         }
     }
 
-    ret
+    Ok(ret)
 }
 
-fn lower_function(ast: &Module, mdef: &mut ModuleDef, input: &Function) -> FunctionDef {
+fn lower_function(
+    ast: &Module,
+    mdef: &mut ModuleDef,
+    input: &Function,
+) -> AssemblerResult<FunctionDef> {
     let mut b = Builder::new(&input.name);
 
     for k in &input.body {
@@ -70,10 +75,13 @@ fn lower_function(ast: &Module, mdef: &mut ModuleDef, input: &Function) -> Funct
     }
 
     for k in &input.body {
-        lower_basic_block(ast, mdef, k, &mut b);
+        lower_basic_block(ast, mdef, k, &mut b)?;
     }
 
-    b.generate()
+    let mut fdef = b.generate();
+    fdef.set_visibility(input.visibility);
+    fdef.set_arity(input.arity);
+    Ok(fdef)
 }
 
 fn lower_name_symbol(ast: &mut Module, fname: &str) -> InternValue {
@@ -85,7 +93,7 @@ fn lower_name_symbol(ast: &mut Module, fname: &str) -> InternValue {
     ast.add_constant(c)
 }
 
-pub fn lower_ast(mut input: Module) -> ModuleDef {
+pub fn lower_ast(mut input: Module) -> AssemblerResult<ModuleDef> {
     let mut ret = ModuleDef::new(&input.name);
 
     for c in &input.constants {
@@ -94,7 +102,11 @@ pub fn lower_ast(mut input: Module) -> ModuleDef {
 
     for t in &input.types {
         let urt = lower_type(t.1);
-        ret.add_named_type(&TypeDef::new(t.0, &urt));
+        let mut tdef = TypeDef::new(t.0, &urt);
+        if input.exported_types.contains(t.0) {
+            tdef.set_visibility(Visibility::Public);
+        }
+        ret.add_named_type(&tdef);
     }
 
     {
@@ -116,9 +128,9 @@ pub fn lower_ast(mut input: Module) -> ModuleDef {
     }
 
     for f in &input.functions {
-        let new_f = lower_function(&input, &mut ret, f);
+        let new_f = lower_function(&input, &mut ret, f)?;
         ret.add_function(new_f);
     }
 
-    ret
+    Ok(ret)
 }
@@ -1,38 +1,70 @@
-pub(crate) mod assembler;
-pub mod ast;
-pub mod lowering;
-pub mod parser;
-pub mod result;
-
-#[cfg(test)]
-pub mod test;
-
-use assembler::do_assemble;
+use assembler::{assembler::do_assemble, bundle::do_bundle};
 use clap::Parser;
 
 #[derive(clap::Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(required = true)]
-    input: String,
+    input: Option<String>,
     #[arg(short, long, default_value = "a.out")]
     output: String,
 }
 
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Packs modules, given as .tas sources or assembled files, into one bundle
+    Bundle {
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        #[arg(short, long, default_value = "a.tbn")]
+        output: String,
+        /// The function the runner starts from; defaults to main in the last module
+        #[arg(short, long)]
+        entry: Option<String>,
+    },
+}
+
 impl Cli {
+    fn input_path(&self) -> &str {
+        self.input.as_deref().unwrap_or_default()
+    }
+
     fn try_read_input(&self) -> std::io::Result<String> {
-        std::fs::read_to_string(&self.input)
+        std::fs::read_to_string(self.input_path())
+    }
+}
+
+fn bundle(inputs: &[String], output: &str, entry: Option<&str>) {
+    let bundle = do_bundle(inputs, entry);
+    if let Err(err) = bundle {
+        panic!("error: {err}");
+    }
+    let result = std::fs::write(output, bundle.unwrap());
+    if let Err(err) = result {
+        panic!("error: {err}");
     }
 }
 
 fn main() {
     let args = Cli::parse();
+    if let Some(Command::Bundle {
+        inputs,
+        output,
+        entry,
+    }) = &args.command
+    {
+        bundle(inputs, output, entry.as_deref());
+        return;
+    }
+
     let input = args.try_read_input();
     if let Err(err) = input {
         panic!("error: {err}");
     }
     let input = input.unwrap();
-    let output = do_assemble(&input);
+    let output = do_assemble(&input, Some(args.input_path()));
     if let Err(err) = output {
         panic!("error: {err}");
     }
@@ -3,6 +3,7 @@ pub enum AssemblerError {
     AstGenerationError(String),
     LoweringError(String),
     SerializationError(String),
+    InputError(String),
 }
 
 pub type AssemblerResult<T> = std::result::Result<T, AssemblerError>;
@@ -14,6 +15,7 @@ impl std::fmt::Display for AssemblerError {
             AssemblerError::AstGenerationError(err) => write!(f, "ast creation error: {err}"),
             AssemblerError::LoweringError(err) => write!(f, "lowering error: {err}"),
             AssemblerError::SerializationError(err) => write!(f, "serialization error: {err}"),
+            AssemblerError::InputError(err) => write!(f, "input error: {err}"),
         }
     }
 }
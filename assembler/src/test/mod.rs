@@ -9,15 +9,15 @@ use runtime::{
     values::{record::Record, RuntimeValue},
 };
 
-use crate::assembler::do_assemble;
+use crate::assembler::{assemble_module, do_assemble};
 
 #[allow(dead_code)]
 fn run_source_impl<'a: 'static>(input: &'a str) -> (Environment, RunloopResult) {
     let mut env = Environment::default();
     corelib::register_corelib(&mut env);
 
-    let mdef = do_assemble(input).expect("invalid input");
-    let mdef: ModuleDef = bincode::deserialize(&mdef).expect("invalid bytecode");
+    let mdef = do_assemble(input, None).expect("invalid input");
+    let mdef: ModuleDef = ModuleDef::from_bytes(&mdef).expect("invalid bytecode");
     let rm = RuntimeModule::from(&mdef);
     env.add_module(rm);
 
@@ -189,6 +189,30 @@ fn main
     run_and_check_stack(input, &[RuntimeValue::Integer(42)]);
 }
 
+#[test]
+fn test_lowering_errors() {
+    let input = r#"
+fn main
+  :entry
+    push "zz"
+    ret
+"#;
+    let err = assemble_module(input, None).unwrap_err();
+    assert_eq!("lowering error: invalid const name zz", format!("{err}"));
+
+    let input = r#"
+fn main
+  :entry
+    jtrue :nope
+    ret
+"#;
+    let err = assemble_module(input, None).unwrap_err();
+    assert_eq!(
+        "lowering error: missing target label :nope",
+        format!("{err}")
+    );
+}
+
 #[test]
 fn test_use_named_float_constant() {
     let input = r#"
@@ -781,12 +805,13 @@ fn main
         input,
         RunloopError {
             cur_ptr: 3,
+            instruction: None,
             data: runloop::RunloopErrData::InvalidOperands(
                 RuntimeInstruction::ADD,
                 vec![rv_str!("four"), rv_int!(5)],
             ),
         },
-        Some("com.tukunc.testmodule.doaddition:3\ncom.tukunc.testmodule.main:10"),
+        Some("com.tukunc.testmodule.doaddition:3\ncom.tukunc.testmodule.main:6"),
     );
 }
 
@@ -814,9 +839,10 @@ fn main
         input,
         RunloopError {
             cur_ptr: 0,
+            instruction: None,
             data: runloop::RunloopErrData::EmptyStack,
         },
-        Some("com.tukunc.testmodule.fail:0\ncom.tukunc.testmodule.main:9"),
+        Some("com.tukunc.testmodule.fail:0\ncom.tukunc.testmodule.main:3"),
     );
 }
 
@@ -1094,3 +1120,226 @@ fn main
 "#;
     run_and_check_stack(input, &[rv_bool!(false), rv_bool!(true)]);
 }
+
+#[test]
+fn test_pub_visibility() {
+    let input = r#"
+@modname "com.tukunc.testmodule"
+pub %typedef "pair" = record("integer", "integer")
+%typedef "hidden" = array(2, "integer")
+fn helper
+  :entry
+    ret
+pub fn main
+  :entry
+    ret
+"#;
+    let mdef = do_assemble(input, None).expect("invalid input");
+    let mdef: ModuleDef = ModuleDef::from_bytes(&mdef).expect("invalid bytecode");
+    let rm = RuntimeModule::from(&mdef);
+    assert!(rm.is_function_exported("main"));
+    assert!(!rm.is_function_exported("helper"));
+    assert!(rm.is_named_type_exported("pair"));
+    assert!(!rm.is_named_type_exported("hidden"));
+    assert!(!rm.is_named_type_exported("integer"));
+}
+
+#[test]
+fn test_call_private_function_same_module() {
+    let input = r#"
+@modname "com.tukunc.testmodule"
+%const "five" = 5
+fn callee
+  :entry
+     push "five"
+     ret
+pub fn main
+  :entry
+    fcall "com.tukunc.testmodule.callee"
+    ret
+"#;
+    run_and_check_stack(input, &[RuntimeValue::Integer(5)]);
+}
+
+#[test]
+fn test_fcall_lowers_to_calldirect() {
+    let input = r#"
+@modname "com.tukunc.testmodule"
+fn main
+  :entry
+    fcall "corelib.now"
+    fcall "corelib.now"
+    sub
+    ret
+"#;
+    let mdef = do_assemble(input, None).expect("invalid input");
+    let mdef: ModuleDef = ModuleDef::from_bytes(&mdef).expect("invalid bytecode");
+    assert_eq!(
+        vec!["corelib.now".to_owned()],
+        mdef.call_targets().cloned().collect::<Vec<String>>()
+    );
+    let main = mdef.functions().next().expect("missing main");
+    assert_eq!(
+        Some((RuntimeInstruction::CALLDIRECT(0), 3)),
+        RuntimeInstruction::from_bytecode(main.body(), 0)
+    );
+
+    let mut env = run_and_check_stack(input, &[]);
+    assert!(matches!(env.pop_value(), RuntimeValue::Integer(_)));
+}
+
+#[test]
+fn test_tailcall() {
+    let input = r#"
+@modname "com.tukunc.testmodule"
+fn countdown
+  :entry
+    dup
+    lpush 0
+    eq
+    jtrue :done
+    lpush 1
+    swap
+    sub
+    tailcall "com.tukunc.testmodule.countdown"
+  :done
+    ret
+pub fn main
+  :entry
+    lpush 200000
+    fcall "com.tukunc.testmodule.countdown"
+    ret
+"#;
+    let env = run_and_check_stack(input, &[rv_int!(0)]);
+    assert!(env.is_stack_empty());
+}
+
+#[test]
+fn test_tailcall_unwind() {
+    let input = r#"
+@modname "com.tukunc.testmodule"
+fn fail
+  :entry
+    nop
+    pop
+    ret
+fn forward
+  :entry
+    tailcall "com.tukunc.testmodule.fail"
+pub fn main
+  :entry
+    fcall "com.tukunc.testmodule.forward"
+    ret
+"#;
+    run_and_check_error(
+        input,
+        RunloopError {
+            cur_ptr: 1,
+            instruction: None,
+            data: runloop::RunloopErrData::EmptyStack,
+        },
+        Some("com.tukunc.testmodule.fail:1\ncom.tukunc.testmodule.main:0"),
+    );
+}
+
+#[test]
+fn test_debug_info() {
+    let input = r#"@modname "com.tukunc.testmodule"
+pub fn main
+  :entry
+    lpush 1
+    jump :next
+  :next
+    lpush 2
+    add
+    ret
+"#;
+    let mdef = assemble_module(input, Some("test.tas")).expect("invalid input");
+    assert_eq!(Some("test.tas"), mdef.source());
+    let main = mdef.functions().find(|f| f.name() == "main").unwrap();
+    assert_eq!(Some(0), main.find_label(":entry"));
+    assert_eq!(Some(6), main.find_label(":next"));
+    assert_eq!(
+        vec![(0, 4), (3, 5), (6, 7), (9, 8), (10, 9)],
+        main.lines().cloned().collect::<Vec<(usize, u32)>>()
+    );
+    assert_eq!(Some(7), main.find_line(6));
+    assert_eq!(None, main.find_line(1));
+
+    let mdef = assemble_module(input, None).expect("invalid input");
+    assert_eq!(None, mdef.source());
+}
+
+#[test]
+fn test_make_bundle() {
+    use crate::bundle::make_bundle;
+    use runtime::bundle::Bundle;
+
+    let lib = assemble_module(
+        r#"
+@modname "lib"
+pub fn seven
+  :entry
+    lpush 7
+    ret
+"#,
+        None,
+    )
+    .expect("invalid input");
+    let app = assemble_module(
+        r#"
+@modname "app"
+fn main
+  :entry
+    fcall "lib.seven"
+    ret
+"#,
+        None,
+    )
+    .expect("invalid input");
+
+    let bundle = make_bundle(vec![lib.clone(), app.clone()], None).expect("bundle failed");
+    assert_eq!(Some("app.main"), bundle.entry());
+    assert!(make_bundle(vec![lib.clone()], None)
+        .expect("bundle failed")
+        .entry()
+        .is_none());
+    assert!(make_bundle(vec![lib.clone(), app.clone()], Some("lib.nope")).is_err());
+    assert!(make_bundle(vec![lib.clone(), lib.clone()], None).is_err());
+
+    let bytes = bundle.to_bytes().expect("serialization failed");
+    assert!(Bundle::is_bundle(&bytes));
+    let bundle = Bundle::from_bytes(&bytes).expect("deserialization failed");
+    assert_eq!(Some("app.main"), bundle.entry());
+    assert_eq!(
+        vec!["lib", "app"],
+        bundle.modules().map(|m| m.name()).collect::<Vec<&str>>()
+    );
+    assert!(Bundle::from_bytes(&bytes[1..]).is_err());
+}
+
+#[test]
+fn test_function_arity() {
+    let input = r#"
+@modname "com.tukunc.testmodule"
+fn add(2)
+  :entry
+    add
+    ret
+fn main
+  :entry
+    ret
+"#;
+    let mdef = assemble_module(input, None).expect("invalid input");
+    let arity = |name: &str| mdef.functions().find(|f| f.name() == name).unwrap().arity();
+    assert_eq!(Some(2), arity("add"));
+    assert_eq!(None, arity("main"));
+
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&mdef));
+    assert_eq!(
+        vec![rv_int!(7)],
+        env.call("com.tukunc.testmodule.add", &[rv_int!(3), rv_int!(4)])
+            .unwrap()
+    );
+}
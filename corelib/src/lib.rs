@@ -21,6 +21,8 @@ mod util;
 #[cfg(test)]
 pub mod test;
 
+pub use crate::util::set_print_output;
+
 pub fn register_corelib(env: &mut Environment) {
     let mut rm = RuntimeModule::new("corelib");
     crate::time::register_corelib(&mut rm);
@@ -1,5 +1,6 @@
 use runtime::{
-    environ::Environment, runtime_module::NativeCallable, rv_arr, rv_int, values::RuntimeValue,
+    environ::Environment, runloop::RunloopErrData, runtime_module::NativeCallable, rv_arr, rv_int,
+    values::RuntimeValue,
 };
 
 use crate::util::ArrayCopy;
@@ -46,3 +47,92 @@ fn test_arraycopy() {
         )
     )
 }
+
+#[test]
+fn test_args() {
+    use crate::util::ArgsCallable;
+    use runtime::rv_str;
+
+    let mut env = Environment::default();
+    assert!(ArgsCallable {}.call(&mut env).is_ok());
+    let args = typed_pop!(env, RuntimeValue::Arr);
+    assert!(args.is_empty());
+
+    env.set_args(vec!["one".to_owned(), "two".to_owned()]);
+    assert!(ArgsCallable {}.call(&mut env).is_ok());
+    let args = typed_pop!(env, RuntimeValue::Arr);
+    assert_eq!(2, args.len());
+    assert_eq!(rv_str!("one"), args.get(0));
+    assert_eq!(rv_str!("two"), args.get(1));
+}
+
+#[test]
+fn test_arraycopy_signature() {
+    let mut env = Environment::default();
+    crate::register_corelib(&mut env);
+    let src = rv_arr!(rv_int!(2), rv_int!(4));
+    let dst = rv_arr!(rv_int!(0), rv_int!(0), rv_int!(0));
+
+    let result = env
+        .call(
+            "corelib.arraycopy",
+            &[src.clone(), rv_int!(0), dst.clone(), rv_int!(1), rv_int!(2)],
+        )
+        .unwrap();
+    assert_eq!(vec![rv_arr!(rv_int!(0), rv_int!(2), rv_int!(4))], result);
+
+    // operands in the wrong order are rejected instead of reaching the native
+    let err = env
+        .call(
+            "corelib.arraycopy",
+            &[rv_int!(0), src, rv_int!(1), dst, rv_int!(2)],
+        )
+        .unwrap_err();
+    assert!(matches!(err.data, RunloopErrData::InvalidType(_)));
+}
+
+#[test]
+fn test_print_output() {
+    use std::{cell::RefCell, rc::Rc};
+
+    use runtime::rv_str;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let buffer = SharedBuffer::default();
+    let mut env = Environment::default();
+    crate::register_corelib(&mut env);
+    crate::set_print_output(&mut env, Box::new(buffer.clone()));
+
+    env.call("corelib.print", &[rv_int!(42)]).unwrap();
+    env.call("corelib.print", &[rv_str!("hello")]).unwrap();
+    assert_eq!(
+        "42\nhello\n",
+        String::from_utf8(buffer.0.borrow().clone()).unwrap()
+    );
+
+    struct Closed {}
+    impl std::io::Write for Closed {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    crate::set_print_output(&mut env, Box::new(Closed {}));
+    let err = env.call("corelib.print", &[rv_int!(42)]).unwrap_err();
+    assert!(matches!(err.data, RunloopErrData::OutputFailed(_)));
+}
@@ -1,28 +1,14 @@
 use std::time::SystemTime;
 
-use runtime::{
-    runloop::RunloopResult,
-    runtime_module::{NativeCallable, RuntimeModule},
-    rv_int,
-};
+use runtime::runtime_module::RuntimeModule;
 
-struct NowCallable {}
-impl NativeCallable for NowCallable {
-    fn call(&self, env: &mut runtime::environ::Environment) -> RunloopResult {
-        let now = SystemTime::now();
-        let duration = now
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .expect("not time travel")
-            .as_millis() as u64;
-        env.push_value(rv_int!(duration));
-        Ok(())
-    }
-
-    fn name(&self) -> String {
-        String::from("now")
-    }
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("not time travel")
+        .as_millis() as u64
 }
 
 pub(crate) fn register_corelib(rm: &mut RuntimeModule) {
-    rm.add_function_native(Box::new(NowCallable {}));
+    rm.add_native_fn("now", now);
 }
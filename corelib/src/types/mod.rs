@@ -1,8 +1,13 @@
-use runtime::{runtime_module::RuntimeModule, types::RuntimeType};
+use runtime::{module_definition::Visibility, runtime_module::RuntimeModule, types::RuntimeType};
 
 pub(crate) fn register_corelib(rm: &mut RuntimeModule) {
-    rm.add_named_type(&RuntimeType::Integer.to_typedef("integer"));
-    rm.add_named_type(&RuntimeType::Logical.to_typedef("logical"));
-    rm.add_named_type(&RuntimeType::String.to_typedef("string"));
-    rm.add_named_type(&RuntimeType::Float.to_typedef("float"));
+    let types = [
+        ("integer", RuntimeType::Integer),
+        ("logical", RuntimeType::Logical),
+        ("string", RuntimeType::String),
+        ("float", RuntimeType::Float),
+    ];
+    for (name, t) in types {
+        rm.add_named_type(t.to_typedef(name).set_visibility(Visibility::Public));
+    }
 }
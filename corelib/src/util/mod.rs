@@ -1,51 +1,79 @@
+use std::{io::Write, sync::LazyLock};
+
 use runtime::{
-    runloop::RunloopResult,
+    environ::Environment,
+    native_fn::{NativeSignature, SignatureType},
+    runloop::{RunloopErrData, RunloopError, RunloopResult},
     runtime_module::{NativeCallable, RuntimeModule},
+    types::RuntimeType,
     values::RuntimeValue,
 };
 
+// where corelib.print writes, kept as host data on the Environment; print
+// falls back to stdout when the host has not set one
+struct PrintOutput(Box<dyn Write>);
+
+pub fn set_print_output(env: &mut Environment, out: Box<dyn Write>) {
+    env.set_host_data(PrintOutput(out));
+}
+
 struct PrintCallable {}
 impl NativeCallable for PrintCallable {
-    fn call(&self, env: &mut runtime::environ::Environment) -> RunloopResult {
+    fn call(&self, env: &mut Environment) -> RunloopResult {
         let value = env.pop_value();
-        match value {
-            runtime::values::RuntimeValue::Integer(n) => {
-                println!("{}", n);
-            }
-            runtime::values::RuntimeValue::Logical(x) => {
-                println!("{}", x);
-            }
-            runtime::values::RuntimeValue::Float(x) => {
-                println!("{}", x);
-            }
-            runtime::values::RuntimeValue::String(s) => {
-                println!("{}", s);
-            }
-            runtime::values::RuntimeValue::Function(f) => {
-                println!("{}", f.fullname());
-            }
-            runtime::values::RuntimeValue::Arr(a) => {
-                println!("{}", a);
-            }
-            runtime::values::RuntimeValue::Record(r) => {
-                println!("{}", r);
-            }
-            runtime::values::RuntimeValue::Type(t) => {
-                println!("{}", t);
-            }
-        }
+        let text = match value {
+            RuntimeValue::Integer(n) => n.to_string(),
+            RuntimeValue::Logical(x) => x.to_string(),
+            RuntimeValue::Float(x) => x.to_string(),
+            RuntimeValue::String(s) => s,
+            RuntimeValue::Function(f) => f.fullname(),
+            RuntimeValue::Arr(a) => a.to_string(),
+            RuntimeValue::Record(r) => r.to_string(),
+            RuntimeValue::Type(t) => t.to_string(),
+        };
+        let written = match env.host_data_mut::<PrintOutput>() {
+            Some(PrintOutput(out)) => writeln!(out, "{text}"),
+            None => writeln!(std::io::stdout(), "{text}"),
+        };
+        written.map_err(|err| RunloopError {
+            cur_ptr: 0,
+            instruction: None,
+            data: RunloopErrData::OutputFailed(err.to_string()),
+        })
+    }
+
+    fn name(&self) -> String {
+        String::from("print")
+    }
 
+    fn signature(&self) -> Option<&NativeSignature> {
+        static SIGNATURE: LazyLock<NativeSignature> =
+            LazyLock::new(|| NativeSignature::new(&[SignatureType::Any], &[]));
+        Some(&SIGNATURE)
+    }
+}
+
+pub(crate) struct ArgsCallable {}
+impl NativeCallable for ArgsCallable {
+    fn call(&self, env: &mut Environment) -> RunloopResult {
+        env.push_value(env.args_value());
         Ok(())
     }
 
     fn name(&self) -> String {
-        String::from("print")
+        String::from("args")
+    }
+
+    fn signature(&self) -> Option<&NativeSignature> {
+        static SIGNATURE: LazyLock<NativeSignature> =
+            LazyLock::new(|| NativeSignature::new(&[], &[SignatureType::Array]));
+        Some(&SIGNATURE)
     }
 }
 
 pub(crate) struct ArrayCopy {}
 impl NativeCallable for ArrayCopy {
-    fn call(&self, env: &mut runtime::environ::Environment) -> RunloopResult {
+    fn call(&self, env: &mut Environment) -> RunloopResult {
         let len = typed_pop!(env, RuntimeValue::Integer) as usize;
         let dst_idx = typed_pop!(env, RuntimeValue::Integer) as usize;
         let mut dst = typed_pop!(env, RuntimeValue::Arr);
@@ -63,9 +91,28 @@ impl NativeCallable for ArrayCopy {
     fn name(&self) -> String {
         String::from("arraycopy")
     }
+
+    // src, src_idx, dst, dst_idx, len
+    fn signature(&self) -> Option<&NativeSignature> {
+        static SIGNATURE: LazyLock<NativeSignature> = LazyLock::new(|| {
+            let integer = SignatureType::Of(RuntimeType::Integer);
+            NativeSignature::new(
+                &[
+                    SignatureType::Array,
+                    integer.clone(),
+                    SignatureType::Array,
+                    integer.clone(),
+                    integer,
+                ],
+                &[SignatureType::Array],
+            )
+        });
+        Some(&SIGNATURE)
+    }
 }
 
 pub(crate) fn register_corelib(rm: &mut RuntimeModule) {
     rm.add_function_native(Box::new(PrintCallable {}));
     rm.add_function_native(Box::new(ArrayCopy {}));
+    rm.add_function_native(Box::new(ArgsCallable {}));
 }
@@ -21,6 +21,7 @@ class GenInstructionEnum(EnumVisitor):
         yield "mod jump;"
         yield "mod lpush;"
         yield "mod push;"
+        yield "mod tailcall;"
         yield "mod toslot;"
         yield "use either::Either;"
         yield "use runtime::intern_value::InternValue;"
@@ -26,13 +26,14 @@ class GenInstructionLowering(OpcodeVisitor):
         yield "mod jump;"
         yield "mod lpush;"
         yield "mod push;"
+        yield "mod tailcall;"
         yield "mod toslot;"
         yield "use runtime::{instruction_def::InstructionDef, module_definition::ModuleDef};"
-        yield "use crate::ast::{instructions::Instruction, module::Module};"
+        yield "use crate::{ast::{instructions::Instruction, module::Module}, result::AssemblerResult};"
         yield "macro_rules! trivial_lowering {"
         yield "    ($input:expr, $candidate:ident) => {"
         yield "        if matches!($input, Instruction::$candidate) {"
-        yield "            return vec![InstructionDef::$candidate];"
+        yield "            return Ok(vec![InstructionDef::$candidate]);"
         yield "        }"
         yield "    };"
         yield "}"
@@ -41,7 +42,7 @@ class GenInstructionLowering(OpcodeVisitor):
         yield "    mdef: &mut ModuleDef,"
         yield "    input: &Instruction,"
         yield "    b: &mut runtime::builder::Builder,"
-        yield ") -> Vec<InstructionDef> {"
+        yield ") -> AssemblerResult<Vec<InstructionDef>> {"
 
     def suffix(self):
         yield "    panic!("
@@ -0,0 +1,198 @@
+use std::{collections::BTreeMap, io::Write};
+
+use runtime::{
+    coverage::Coverage, instruction_stream::InstructionStream, module_definition::ModuleDef,
+};
+
+use crate::block_ranges;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockCoverage {
+    pub label: String,
+    pub offset: usize,
+    pub line: Option<u32>,
+    pub instructions: usize,
+    pub covered: usize,
+    pub hits: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCoverage {
+    pub name: String,
+    pub line: Option<u32>,
+    pub hits: u64,
+    pub lines: BTreeMap<u32, u64>,
+    pub blocks: Vec<BlockCoverage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleCoverage {
+    pub module: String,
+    pub source: String,
+    pub functions: Vec<FunctionCoverage>,
+}
+
+impl ModuleCoverage {
+    pub fn lines(&self) -> BTreeMap<u32, u64> {
+        let mut lines = BTreeMap::new();
+        for f in &self.functions {
+            for (line, hits) in &f.lines {
+                *lines.entry(*line).or_default() += hits;
+            }
+        }
+        lines
+    }
+}
+
+fn function_coverage(
+    coverage: &Coverage,
+    module: &str,
+    fdef: &runtime::module_definition::FunctionDef,
+) -> FunctionCoverage {
+    let name = format!("{}.{}", module, fdef.name());
+    let code = InstructionStream::from(fdef.body());
+    let offsets = (0..code.len())
+        .map(|idx| code.offset(idx))
+        .collect::<Vec<usize>>();
+
+    // a line counts as executed as often as its first instruction
+    let mut lines: BTreeMap<u32, u64> = BTreeMap::new();
+    let mut line_starts: BTreeMap<u32, usize> = BTreeMap::new();
+    for (offset, line) in fdef.lines() {
+        let start = line_starts.entry(*line).or_insert(*offset);
+        if *offset <= *start {
+            *start = *offset;
+            lines.insert(*line, coverage.count(&name, *offset));
+        }
+    }
+
+    let mut blocks = vec![];
+    for (label, range) in block_ranges(Some(fdef)) {
+        let in_block = offsets
+            .iter()
+            .filter(|o| range.contains(o))
+            .collect::<Vec<&usize>>();
+        if in_block.is_empty() {
+            continue;
+        }
+        blocks.push(BlockCoverage {
+            label,
+            offset: range.start,
+            line: fdef.find_line(range.start),
+            instructions: in_block.len(),
+            covered: in_block
+                .iter()
+                .filter(|o| coverage.count(&name, ***o) > 0)
+                .count(),
+            hits: coverage.count(&name, range.start),
+        });
+    }
+
+    FunctionCoverage {
+        line: fdef.find_line(0),
+        hits: coverage.count(&name, 0),
+        name,
+        lines,
+        blocks,
+    }
+}
+
+pub fn coverage_report(coverage: &Coverage, module_defs: &[ModuleDef]) -> Vec<ModuleCoverage> {
+    module_defs
+        .iter()
+        .map(|mdef| {
+            let mut functions = mdef
+                .functions()
+                .map(|fdef| function_coverage(coverage, mdef.name(), fdef))
+                .collect::<Vec<FunctionCoverage>>();
+            functions.sort_by(|a, b| a.name.cmp(&b.name));
+            ModuleCoverage {
+                module: mdef.name().to_owned(),
+                source: mdef
+                    .source()
+                    .map(str::to_owned)
+                    .unwrap_or(format!("{}.tas", mdef.name())),
+                functions,
+            }
+        })
+        .collect()
+}
+
+pub fn write_lcov<W: Write>(modules: &[ModuleCoverage], out: &mut W) -> std::io::Result<()> {
+    for m in modules {
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", m.source)?;
+        for f in &m.functions {
+            if let Some(line) = f.line {
+                writeln!(out, "FN:{},{}", line, f.name)?;
+            }
+        }
+        for f in &m.functions {
+            if f.line.is_some() {
+                writeln!(out, "FNDA:{},{}", f.hits, f.name)?;
+            }
+        }
+        let with_lines = m.functions.iter().filter(|f| f.line.is_some());
+        writeln!(out, "FNF:{}", with_lines.clone().count())?;
+        writeln!(out, "FNH:{}", with_lines.filter(|f| f.hits > 0).count())?;
+        let lines = m.lines();
+        for (line, hits) in &lines {
+            writeln!(out, "DA:{},{}", line, hits)?;
+        }
+        writeln!(out, "LF:{}", lines.len())?;
+        writeln!(out, "LH:{}", lines.values().filter(|h| **h > 0).count())?;
+        writeln!(out, "end_of_record")?;
+    }
+    Ok(())
+}
+
+fn percent(covered: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        covered as f64 * 100.0 / total as f64
+    }
+}
+
+pub fn write_summary<W: Write>(modules: &[ModuleCoverage], out: &mut W) -> std::io::Result<()> {
+    for m in modules {
+        let lines = m.lines();
+        let lines_hit = lines.values().filter(|h| **h > 0).count();
+        let blocks = m.functions.iter().flat_map(|f| f.blocks.iter());
+        let blocks_total = blocks.clone().count();
+        let blocks_hit = blocks.filter(|b| b.covered > 0).count();
+        writeln!(
+            out,
+            "{} ({}): lines {}/{} ({:.1}%), blocks {}/{} ({:.1}%)",
+            m.module,
+            m.source,
+            lines_hit,
+            lines.len(),
+            percent(lines_hit, lines.len()),
+            blocks_hit,
+            blocks_total,
+            percent(blocks_hit, blocks_total)
+        )?;
+        for f in &m.functions {
+            let covered = f.blocks.iter().map(|b| b.covered).sum::<usize>();
+            let total = f.blocks.iter().map(|b| b.instructions).sum::<usize>();
+            writeln!(
+                out,
+                "  {}: instructions {}/{} ({:.1}%)",
+                f.name,
+                covered,
+                total,
+                percent(covered, total)
+            )?;
+            for b in f.blocks.iter().filter(|b| b.covered < b.instructions) {
+                let line = b.line.map(|l| format!(", line {l}")).unwrap_or_default();
+                writeln!(
+                    out,
+                    "    block {} at offset {}{}: {}/{} instructions",
+                    b.label, b.offset, line, b.covered, b.instructions
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
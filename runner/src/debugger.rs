@@ -0,0 +1,222 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use runtime::{
+    environ::Environment,
+    instruction_runtime::RuntimeInstruction,
+    observer::{ExecutionObserver, InstructionEvent, ObserverAction},
+    runloop::{resume_run, run_loop_resumable, RunloopResult, RunloopStatus, SuspendedRun},
+    runtime_module::RuntimeCallable,
+    values::RuntimeValue,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StepMode {
+    Step,
+    StepOver(usize),
+    Finish(usize),
+    Continue,
+}
+
+struct Stop {
+    function: String,
+    offset: usize,
+    inst: RuntimeInstruction,
+    breakpoint: bool,
+}
+
+struct DebugState {
+    mode: StepMode,
+    breakpoints: HashSet<(String, usize)>,
+    stop: Option<Stop>,
+}
+
+struct StepHook {
+    state: Rc<RefCell<DebugState>>,
+}
+
+impl ExecutionObserver for StepHook {
+    fn before_instruction(&mut self, event: &InstructionEvent) -> ObserverAction {
+        let depth = event.depth;
+        let offset = event.offset;
+        let mut state = self.state.borrow_mut();
+        let stepped = match state.mode {
+            StepMode::Step => true,
+            StepMode::StepOver(d) => depth <= d,
+            StepMode::Finish(d) => depth < d,
+            StepMode::Continue => false,
+        };
+        let fullname = event.function.fullname();
+        let breakpoint = !state.breakpoints.is_empty()
+            && state.breakpoints.contains(&(fullname.clone(), offset));
+        if stepped || breakpoint {
+            state.stop = Some(Stop {
+                function: fullname,
+                offset,
+                inst: event.instruction.clone(),
+                breakpoint,
+            });
+            ObserverAction::Pause
+        } else {
+            ObserverAction::Continue
+        }
+    }
+}
+
+pub enum DebugOutcome {
+    Finished(RunloopResult),
+    Quit,
+}
+
+// resolves "module.fn:offset" or "module.fn:label" to a function fullname and
+// a bytecode offset; labels are looked up in the Environment, which loads the
+// module through its module loader if needed
+pub fn parse_breakpoint(spec: &str, env: &Environment) -> Result<(String, usize), String> {
+    let (fullname, location) = spec.rsplit_once(':').ok_or(format!(
+        "expected module.fn:offset or module.fn:label, got {spec}"
+    ))?;
+    if let Ok(offset) = location.parse::<usize>() {
+        return Ok((fullname.to_owned(), offset));
+    }
+
+    let (module, function) = fullname.rsplit_once('.').ok_or(format!(
+        "expected a module qualified function, got {fullname}"
+    ))?;
+    let m = env
+        .find_or_load_module(module)
+        .map_err(|err| format!("unable to load module {module}: {err}"))?
+        .ok_or(format!("unknown module {module}"))?;
+    let f = m
+        .find_function(function)
+        .ok_or(format!("unknown function {fullname}"))?;
+    // the assembler keeps the leading colon of block labels
+    let offset = f
+        .find_label(location)
+        .or_else(|| f.find_label(&format!(":{location}")))
+        .ok_or(format!("unknown label {location} in {fullname}"))?;
+    Ok((fullname.to_owned(), offset))
+}
+
+fn format_values(values: &[RuntimeValue]) -> String {
+    values
+        .iter()
+        .map(|v| format!("{v}"))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn print_stop<W: Write>(out: &mut W, run: &SuspendedRun, stop: &Stop) -> std::io::Result<()> {
+    let reason = if stop.breakpoint { " (breakpoint)" } else { "" };
+    writeln!(
+        out,
+        "stopped at {}:{}{}: {:?}",
+        stop.function, stop.offset, reason, stop.inst
+    )?;
+    writeln!(out, "stack: [{}]", format_values(run.stack()))?;
+    let slots = run.frames().last().map(|f| f.get_slots()).unwrap_or(&[]);
+    writeln!(out, "slots: [{}]", format_values(slots))?;
+    print_frames(out, run)
+}
+
+fn print_frames<W: Write>(out: &mut W, run: &SuspendedRun) -> std::io::Result<()> {
+    writeln!(out, "frames:")?;
+    for frame in run.frames().iter().rev() {
+        writeln!(out, "  {frame}")?;
+    }
+    Ok(())
+}
+
+pub fn debug_run<R: BufRead, W: Write>(
+    env: &mut Environment,
+    main: &RuntimeCallable,
+    mut input: R,
+    out: &mut W,
+) -> std::io::Result<DebugOutcome> {
+    let state = Rc::new(RefCell::new(DebugState {
+        mode: StepMode::Step,
+        breakpoints: HashSet::new(),
+        stop: None,
+    }));
+    let observer = env.add_observer(Box::new(StepHook {
+        state: state.clone(),
+    }));
+    let base = env.call_depth();
+
+    let mut status = run_loop_resumable(main, env);
+    let outcome = loop {
+        let run = match status {
+            Ok(RunloopStatus::Finished) => break DebugOutcome::Finished(Ok(())),
+            Ok(RunloopStatus::Suspended(run)) => run,
+            Err(err) => {
+                writeln!(out, "error: {err}")?;
+                writeln!(out, "{}", env.print_unwind())?;
+                break DebugOutcome::Finished(Err(err));
+            }
+        };
+
+        if let Some(stop) = &state.borrow().stop {
+            print_stop(out, &run, stop)?;
+        }
+
+        let depth = base + run.depth();
+        let mode = loop {
+            write!(out, "(tdb) ")?;
+            out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                break None;
+            }
+            let mut words = line.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("s" | "step"), None) => break Some(StepMode::Step),
+                (Some("n" | "next"), None) => break Some(StepMode::StepOver(depth)),
+                (Some("f" | "finish"), None) => break Some(StepMode::Finish(depth)),
+                (Some("c" | "continue"), None) => break Some(StepMode::Continue),
+                (Some("q" | "quit"), None) => break None,
+                (Some("b" | "break"), Some(spec)) => match parse_breakpoint(spec, env) {
+                    Ok((f, offset)) => {
+                        writeln!(out, "breakpoint set at {f}:{offset}")?;
+                        state.borrow_mut().breakpoints.insert((f, offset));
+                    }
+                    Err(err) => writeln!(out, "error: {err}")?,
+                },
+                (Some("d" | "delete"), Some(spec)) => match parse_breakpoint(spec, env) {
+                    Ok((f, offset)) => {
+                        if state.borrow_mut().breakpoints.remove(&(f.clone(), offset)) {
+                            writeln!(out, "breakpoint removed at {f}:{offset}")?;
+                        } else {
+                            writeln!(out, "no breakpoint at {f}:{offset}")?;
+                        }
+                    }
+                    Err(err) => writeln!(out, "error: {err}")?,
+                },
+                (Some("bt" | "frames"), None) => print_frames(out, &run)?,
+                (Some("p" | "print"), None) => {
+                    if let Some(stop) = &state.borrow().stop {
+                        print_stop(out, &run, stop)?;
+                    }
+                }
+                (None, _) => {}
+                _ => writeln!(
+                    out,
+                    "commands: step, next, finish, continue, break <bp>, delete <bp>, frames, print, quit"
+                )?,
+            }
+        };
+
+        match mode {
+            Some(mode) => {
+                state.borrow_mut().mode = mode;
+                status = resume_run(run, env);
+            }
+            None => break DebugOutcome::Quit,
+        }
+    };
+
+    env.remove_observer(observer);
+    Ok(outcome)
+}
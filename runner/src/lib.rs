@@ -1,8 +1,80 @@
-use runtime::module_definition::ModuleDef;
+pub mod coverage_report;
+pub mod debugger;
+pub mod module_path;
+pub mod profiler;
+pub mod repl;
+pub mod tracer;
+
+use std::ops::Range;
+
+use runtime::{
+    bundle::Bundle,
+    environ::Environment,
+    module_definition::{FunctionDef, ModuleDef},
+    runloop::{RunloopErrData, RunloopError, RunloopResult},
+};
+
+pub const EXIT_RUNTIME_ERROR: i32 = 1;
+pub const EXIT_LOAD_ERROR: i32 = 2;
+pub const EXIT_LINK_ERROR: i32 = 3;
+// reading from the terminal or writing a trace or report failed
+pub const EXIT_IO_ERROR: i32 = 4;
+
+// an integer left on top of the stack by main becomes the exit status; the
+// process only keeps the low 8 bits, so anything above 255 exits with 255
+// rather than wrapping around to success. errors map to the runtime, link or
+// load failure codes
+pub fn exit_code(result: &RunloopResult, env: &Environment) -> i32 {
+    match result {
+        Ok(_) => env
+            .stack_values()
+            .last()
+            .and_then(|v| v.as_integer())
+            .map(|x| i32::from(u8::try_from(*x).unwrap_or(u8::MAX)))
+            .unwrap_or(0),
+        Err(err) if err.data.is_link_error() => EXIT_LINK_ERROR,
+        Err(RunloopError {
+            data: RunloopErrData::ModuleLoadFailed(..),
+            ..
+        }) => EXIT_LOAD_ERROR,
+        Err(_) => EXIT_RUNTIME_ERROR,
+    }
+}
+
+// splits a function body at its labels, sorted by offset; code before the
+// first label forms an unnamed block at offset 0 and the last block runs to
+// the end of the function
+pub fn block_ranges(fdef: Option<&FunctionDef>) -> Vec<(String, Range<usize>)> {
+    let mut labels = fdef
+        .map(|f| f.labels().cloned().collect::<Vec<(String, usize)>>())
+        .unwrap_or_default();
+    labels.sort_by_key(|(_, offset)| *offset);
+    if labels.first().map(|(_, o)| *o) != Some(0) {
+        labels.insert(0, (String::default(), 0));
+    }
+    let ends = labels
+        .iter()
+        .skip(1)
+        .map(|(_, o)| *o)
+        .chain(std::iter::once(usize::MAX))
+        .collect::<Vec<usize>>();
+    labels
+        .into_iter()
+        .zip(ends)
+        .map(|((label, start), end)| (label, start..end))
+        .collect()
+}
 
 pub trait ModuleSource {
     fn description(&self) -> String;
     fn read(&self) -> std::io::Result<ModuleDef>;
+
+    // sources of a single module read as a bundle without an entry function
+    fn read_bundle(&self) -> std::io::Result<Bundle> {
+        let mut bundle = Bundle::new(None);
+        bundle.add_module(self.read()?);
+        Ok(bundle)
+    }
 }
 
 pub struct FileModuleSource {
@@ -23,16 +95,115 @@ impl ModuleSource for FileModuleSource {
     }
 
     fn read(&self) -> std::io::Result<ModuleDef> {
-        let bytes = std::fs::read(&self.path);
-        match bytes {
-            Ok(bytes) => {
-                let mdef: Result<ModuleDef, Box<bincode::ErrorKind>> = bincode::deserialize(&bytes);
-                match mdef {
-                    Ok(mdef) => Ok(mdef),
-                    Err(err) => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, err)),
-                }
-            }
-            Err(err) => Err(err),
+        let bytes = std::fs::read(&self.path)?;
+        ModuleDef::from_bytes(&bytes)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+}
+
+// assembles a text source file in memory
+pub struct AssemblyModuleSource {
+    path: String,
+}
+
+impl AssemblyModuleSource {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
         }
     }
 }
+
+impl ModuleSource for AssemblyModuleSource {
+    fn description(&self) -> String {
+        format!("file: {}", self.path)
+    }
+
+    fn read(&self) -> std::io::Result<ModuleDef> {
+        let src = std::fs::read_to_string(&self.path)?;
+        assembler::assembler::assemble_module(&src, Some(&self.path))
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{err}")))
+    }
+}
+
+// loads every module of a bundle file
+pub struct BundleModuleSource {
+    path: String,
+}
+
+impl BundleModuleSource {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+        }
+    }
+}
+
+impl ModuleSource for BundleModuleSource {
+    fn description(&self) -> String {
+        format!("bundle: {}", self.path)
+    }
+
+    // only bundles of a single module can stand in for one module
+    fn read(&self) -> std::io::Result<ModuleDef> {
+        let mut modules = self.read_bundle()?.into_modules();
+        if modules.len() == 1 {
+            Ok(modules.remove(0))
+        } else {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("bundle holds {} modules", modules.len()),
+            ))
+        }
+    }
+
+    fn read_bundle(&self) -> std::io::Result<Bundle> {
+        let bytes = std::fs::read(&self.path)?;
+        Bundle::from_bytes(&bytes)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileKind {
+    Assembly,
+    Bytecode,
+    Bundle,
+}
+
+// bundles and serialized modules start with their magic bytes; anything else
+// is taken for assembler source
+fn sniff_file_kind(path: &str) -> std::io::Result<FileKind> {
+    let mut head = [0u8; 8];
+    let mut file = std::fs::File::open(path)?;
+    let n = std::io::Read::read(&mut file, &mut head)?;
+    if Bundle::is_bundle(&head[..n]) {
+        Ok(FileKind::Bundle)
+    } else if ModuleDef::is_module(&head[..n]) {
+        Ok(FileKind::Bytecode)
+    } else {
+        Ok(FileKind::Assembly)
+    }
+}
+
+// picks the module source for a path by its extension, falling back to the
+// contents of the file
+pub fn module_source_for_path(path: &str) -> Box<dyn ModuleSource> {
+    let extension = std::path::Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str());
+    let kind = match extension {
+        Some("tas") => FileKind::Assembly,
+        Some("tbc") => FileKind::Bytecode,
+        Some("tbn") => FileKind::Bundle,
+        _ => sniff_file_kind(path).unwrap_or(FileKind::Bytecode),
+    };
+    match kind {
+        FileKind::Assembly => Box::new(AssemblyModuleSource::new(path)),
+        FileKind::Bytecode => Box::new(FileModuleSource::new(path)),
+        FileKind::Bundle => Box::new(BundleModuleSource::new(path)),
+    }
+}
+
+#[cfg(test)]
+pub mod test;
@@ -1,45 +1,135 @@
+use std::{cell::RefCell, rc::Rc};
+
 use clap::Parser;
 use corelib::register_corelib;
-use runner::{FileModuleSource, ModuleSource};
+use runner::{
+    coverage_report::{coverage_report, write_lcov, write_summary},
+    debugger::{debug_run, DebugOutcome},
+    exit_code,
+    module_path::{ModulePath, ModulePathLoader, MODULE_PATH_ENV_VAR},
+    module_source_for_path,
+    profiler::{Profile, ProfileObserver},
+    repl::Repl,
+    tracer::TraceObserver,
+    ModuleSource, EXIT_IO_ERROR, EXIT_LINK_ERROR, EXIT_LOAD_ERROR,
+};
 use runtime::{
-    environ::Environment, module_definition::ModuleDef, runloop::run_loop,
+    coverage::{Coverage, CoverageObserver},
+    environ::{Environment, LookupError},
+    limits::Limits,
+    log::{self, FileWriter, LogLevel},
+    module_definition::ModuleDef,
+    observer::{LogObserver, LOG_RUNLOOP},
+    runloop::{run_loop, RunloopResult},
     runtime_module::RuntimeModule,
 };
 
 #[derive(clap::Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    #[arg(required = true)]
     inputs: Vec<String>,
+    // directories to load modules from on demand, separated like PATH; the
+    // TUKUN_MODULE_PATH environment variable is searched after them
+    #[arg(long)]
+    module_path: Vec<String>,
     #[arg(short, long, default_value = "")]
     main_f: String,
     #[arg(short, long, default_value_t = false)]
     dump_stack: bool,
     #[arg(short, long, default_value_t = false)]
     omit_corelib: bool,
+    #[arg(long, default_value_t = false)]
+    debug: bool,
+    #[arg(long, default_value_t = false)]
+    repl: bool,
+    #[arg(long, default_value_t = false)]
+    coverage: bool,
+    #[arg(long, default_value = "coverage.lcov")]
+    coverage_lcov: String,
+    #[arg(long, default_value = "coverage.txt")]
+    coverage_report: String,
+    #[arg(long, default_value_t = false)]
+    profile: bool,
+    #[arg(long, default_value = "profile.txt")]
+    profile_report: String,
+    #[arg(long, default_value = "profile.folded")]
+    profile_folded: String,
+    #[arg(long)]
+    trace: Option<String>,
+    #[arg(long, default_value_t = 0)]
+    trace_top: usize,
+    #[arg(long)]
+    max_instructions: Option<u64>,
+    #[arg(long)]
+    max_elements: Option<usize>,
+    #[arg(long)]
+    max_string_constant_bytes: Option<usize>,
+    #[arg(long)]
+    max_slots: Option<usize>,
+    #[arg(long)]
+    max_stack_depth: Option<usize>,
+    #[arg(long)]
+    log: Option<String>,
+    #[arg(long)]
+    log_file: Option<String>,
+    // arguments after -- are handed to the program
+    #[arg(last = true)]
+    program_args: Vec<String>,
+}
+
+fn report_result(result: &RunloopResult, env: &Environment) {
+    if let Err(err) = result {
+        eprintln!("error: {err}");
+        eprintln!("{}", env.print_unwind());
+    }
 }
+
 fn main() {
     let args = Cli::parse();
 
+    if let Some(spec) = &args.log {
+        if let Err(err) = log::configure(spec) {
+            eprintln!("invalid --log: {err}");
+        }
+    }
+    if let Some(path) = &args.log_file {
+        match FileWriter::create(path) {
+            Ok(writer) => {
+                log::set_writer(Box::new(writer));
+            }
+            Err(err) => eprintln!("error creating log file {path}: {err}"),
+        }
+    }
+
     let mut env = Environment::default();
+    env.set_args(args.program_args.clone());
+    env.set_instruction_budget(args.max_instructions);
+    env.set_limits(Limits {
+        max_live_elements: args.max_elements,
+        max_string_constant_bytes: args.max_string_constant_bytes,
+        max_slots: args.max_slots,
+        max_stack_depth: args.max_stack_depth,
+    });
 
     let module_sources = args
         .inputs
         .iter()
-        .map(|input| {
-            let b: Box<dyn ModuleSource> = Box::new(FileModuleSource::new(input));
-            b
-        })
+        .map(|input| module_source_for_path(input))
         .collect::<Vec<Box<dyn ModuleSource>>>();
 
     let mut module_defs: Vec<ModuleDef> = Vec::new();
+    let mut entry: Option<String> = None;
     for ms in module_sources {
-        match ms.read() {
-            Ok(mdef) => {
-                module_defs.push(mdef);
+        match ms.read_bundle() {
+            Ok(bundle) => {
+                if entry.is_none() {
+                    entry = bundle.entry().map(str::to_owned);
+                }
+                module_defs.extend(bundle.into_modules());
             }
             Err(err) => {
-                panic!("error trying to read {}: {}", ms.description(), err);
+                eprintln!("error trying to read {}: {}", ms.description(), err);
+                std::process::exit(EXIT_LOAD_ERROR);
             }
         }
     }
@@ -48,25 +138,158 @@ fn main() {
         env.add_module(rm);
     });
 
+    let mut module_path = ModulePath::default();
+    args.module_path
+        .iter()
+        .for_each(|spec| module_path.add_dirs(spec));
+    module_path.add_dirs(&std::env::var(MODULE_PATH_ENV_VAR).unwrap_or_default());
+
+    if args.repl {
+        let limits = env.limits();
+        let omit_corelib = args.omit_corelib;
+        let program_args = args.program_args.clone();
+        let mut repl = Repl::new(Box::new(move || {
+            let mut env = Environment::default();
+            env.set_args(program_args.clone());
+            env.set_limits(limits);
+            module_defs.iter().map(RuntimeModule::from).for_each(|rm| {
+                env.add_module(rm);
+            });
+            env.set_module_loader(Some(Box::new(ModulePathLoader::new(module_path.clone()))));
+            if !omit_corelib {
+                register_corelib(&mut env);
+            }
+            env
+        }));
+        repl.set_instruction_budget(args.max_instructions);
+        let stdin = std::io::stdin();
+        let mut stdout = std::io::stdout();
+        if let Err(err) = repl.run(stdin.lock(), &mut stdout) {
+            eprintln!("error: repl i/o failed: {err}");
+            std::process::exit(EXIT_IO_ERROR);
+        }
+        return;
+    }
+
+    let loader = ModulePathLoader::new(module_path);
+    let loaded_defs = loader.loaded();
+    env.set_module_loader(Some(Box::new(loader)));
+
     if !args.omit_corelib {
         register_corelib(&mut env);
     }
 
+    if log::is_enabled(&LOG_RUNLOOP, LogLevel::Debug) {
+        env.add_observer(Box::new(LogObserver {}));
+    }
+
+    let tracer = args
+        .trace
+        .as_ref()
+        .map(|path| match std::fs::File::create(path) {
+            Ok(file) => env.add_observer(Box::new(TraceObserver::new(
+                std::io::BufWriter::new(file),
+                args.trace_top,
+            ))),
+            Err(err) => {
+                eprintln!("error: unable to create trace file {path}: {err}");
+                std::process::exit(EXIT_IO_ERROR);
+            }
+        });
+
+    let profile = Rc::new(RefCell::new(Profile::default()));
+    let profiler = if args.profile {
+        Some(env.add_observer(Box::new(ProfileObserver::new(&profile))))
+    } else {
+        None
+    };
+
+    let coverage = Rc::new(RefCell::new(Coverage::default()));
+    let coverage_observer = if args.coverage {
+        Some(env.add_observer(Box::new(CoverageObserver::new(&coverage))))
+    } else {
+        None
+    };
+
     let main_f = if args.main_f.is_empty() {
-        if let Some(last) = module_defs.last() {
+        if let Some(entry) = entry {
+            entry
+        } else if let Some(last) = module_defs.last() {
             format!("{}.main", last.name())
         } else {
-            panic!("unable to infer main function to run");
+            eprintln!("error: unable to infer the main function to run");
+            std::process::exit(EXIT_LINK_ERROR);
         }
     } else {
         args.main_f
     };
 
-    match env.lookup_function(&main_f) {
-        Some(f) => {
-            println!("main function result: {:?}", run_loop(&f, &mut env));
+    // main only finds the argument array on its stack when arguments were
+    // given after --; otherwise the stack starts empty and programs can still
+    // read them through the corelib args function
+    if !args.program_args.is_empty() {
+        env.push_value(env.args_value());
+    }
+
+    let code = match env.try_lookup_function(&main_f) {
+        Ok(f) if args.debug => {
+            let stdin = std::io::stdin();
+            let mut stdout = std::io::stdout();
+            match debug_run(&mut env, &f, stdin.lock(), &mut stdout) {
+                Ok(DebugOutcome::Finished(result)) => exit_code(&result, &env),
+                Ok(DebugOutcome::Quit) => 0,
+                Err(err) => {
+                    eprintln!("error: debugger i/o failed: {err}");
+                    EXIT_IO_ERROR
+                }
+            }
+        }
+        Ok(f) => {
+            let result = run_loop(&f, &mut env);
+            report_result(&result, &env);
+            exit_code(&result, &env)
+        }
+        Err(LookupError::LoadFailed(m, err)) => {
+            eprintln!("error: unable to load module {m}: {err}");
+            EXIT_LOAD_ERROR
+        }
+        Err(_) => {
+            eprintln!("error: main function {main_f} not found");
+            EXIT_LINK_ERROR
+        }
+    };
+
+    if let Some(tracer) = tracer {
+        env.remove_observer(tracer);
+    }
+
+    module_defs.append(&mut loaded_defs.borrow_mut());
+
+    if let Some(profiler) = profiler {
+        env.remove_observer(profiler);
+        let mut profile = profile.borrow_mut();
+        profile.finish();
+        let written = std::fs::File::create(&args.profile_report)
+            .and_then(|mut f| profile.write_report(&module_defs, &mut f))
+            .and_then(|_| std::fs::File::create(&args.profile_folded))
+            .and_then(|mut f| profile.write_folded(&mut f));
+        if let Err(err) = written {
+            eprintln!("error: unable to write profile: {err}");
+            std::process::exit(EXIT_IO_ERROR);
+        }
+    }
+
+    if let Some(coverage_observer) = coverage_observer {
+        env.remove_observer(coverage_observer);
+        let report = coverage_report(&coverage.borrow(), &module_defs);
+        let written = std::fs::File::create(&args.coverage_lcov)
+            .and_then(|mut f| write_lcov(&report, &mut f))
+            .and_then(|_| std::fs::File::create(&args.coverage_report))
+            .and_then(|mut f| write_summary(&report, &mut f));
+        if let Err(err) = written {
+            eprintln!("error: unable to write coverage: {err}");
+            std::process::exit(EXIT_IO_ERROR);
         }
-        None => panic!("unable to find main function"),
     }
 
     if args.dump_stack {
@@ -75,4 +298,6 @@ fn main() {
             println!("{v}");
         }
     }
+
+    std::process::exit(code);
 }
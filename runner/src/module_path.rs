@@ -0,0 +1,105 @@
+use std::{
+    cell::RefCell,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+};
+
+use runtime::{module_definition::ModuleDef, module_loader::ModuleLoader};
+
+use crate::module_source_for_path;
+
+pub const MODULE_PATH_ENV_VAR: &str = "TUKUN_MODULE_PATH";
+
+// directories searched for modules; com.foo.bar is found at com/foo/bar.tbc
+// or com/foo/bar.tas below one of them
+#[derive(Default, Debug, Clone)]
+pub struct ModulePath {
+    dirs: Vec<PathBuf>,
+}
+
+impl ModulePath {
+    // spec is a list of directories separated like PATH
+    pub fn add_dirs(&mut self, spec: &str) {
+        self.dirs.extend(std::env::split_paths(spec));
+    }
+
+    pub fn add_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        self.dirs.push(dir.as_ref().to_path_buf());
+    }
+
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+
+    // names that could reach outside the search directories, through a
+    // separator, a parent or root component or an empty segment, resolve to
+    // nothing
+    pub fn resolve(&self, module: &str) -> Option<PathBuf> {
+        if !module.split('.').all(is_plain_segment) {
+            return None;
+        }
+        let relative = module.split('.').collect::<PathBuf>();
+        for dir in &self.dirs {
+            for extension in ["tbc", "tas"] {
+                let candidate = dir.join(&relative).with_extension(extension);
+                if candidate.is_file() {
+                    return Some(candidate);
+                }
+            }
+        }
+        None
+    }
+}
+
+fn is_plain_segment(segment: &str) -> bool {
+    !segment.contains(['/', '\\'])
+        && matches!(
+            Path::new(segment)
+                .components()
+                .collect::<Vec<_>>()
+                .as_slice(),
+            [Component::Normal(_)]
+        )
+}
+
+// loads modules on demand from a ModulePath; the definitions it loaded are
+// kept for reports that need them after the run
+pub struct ModulePathLoader {
+    path: ModulePath,
+    loaded: Rc<RefCell<Vec<ModuleDef>>>,
+}
+
+impl ModulePathLoader {
+    pub fn new(path: ModulePath) -> Self {
+        Self {
+            path,
+            loaded: Default::default(),
+        }
+    }
+
+    pub fn loaded(&self) -> Rc<RefCell<Vec<ModuleDef>>> {
+        self.loaded.clone()
+    }
+}
+
+impl ModuleLoader for ModulePathLoader {
+    fn load_module(&mut self, name: &str) -> Result<Option<ModuleDef>, String> {
+        let Some(path) = self.path.resolve(name) else {
+            return Ok(None);
+        };
+        let source = module_source_for_path(&path.to_string_lossy());
+        let mdef = source
+            .read()
+            .map_err(|err| format!("error trying to read {}: {}", source.description(), err))?;
+        if mdef.name() != name {
+            return Err(format!(
+                "{} defines module {} instead of {}",
+                source.description(),
+                mdef.name(),
+                name
+            ));
+        }
+        self.loaded.borrow_mut().push(mdef.clone());
+        Ok(Some(mdef))
+    }
+}
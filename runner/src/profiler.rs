@@ -0,0 +1,298 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::Write,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use runtime::{
+    module_definition::ModuleDef,
+    observer::{ExecutionObserver, InstructionEvent, ObserverAction},
+    runtime_module::RuntimeCallable,
+};
+
+use crate::block_ranges;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub name: String,
+    pub calls: u64,
+    pub instructions_inclusive: u64,
+    pub instructions_exclusive: u64,
+    pub time_inclusive: Duration,
+    pub time_exclusive: Duration,
+    pub offsets: HashMap<usize, u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockStats {
+    pub function: String,
+    pub label: String,
+    pub offset: usize,
+    pub executions: u64,
+    pub instructions: u64,
+}
+
+struct StackNode {
+    parent: Option<usize>,
+    function: usize,
+    instructions: u64,
+}
+
+struct ActiveFrame {
+    function: usize,
+    node: usize,
+    entered: Instant,
+    child_time: Duration,
+    instructions: u64,
+    child_instructions: u64,
+    // recursive activations only add to the inclusive totals once
+    outermost: bool,
+}
+
+// instruction counts and wall time per function, gathered by a
+// ProfileObserver; folded stacks are weighted by instructions executed
+#[derive(Default)]
+pub struct Profile {
+    functions: Vec<FunctionStats>,
+    by_name: HashMap<String, usize>,
+    nodes: Vec<StackNode>,
+    node_index: HashMap<(Option<usize>, usize), usize>,
+    active: Vec<ActiveFrame>,
+    active_counts: Vec<usize>,
+}
+
+impl Profile {
+    fn function_index(&mut self, f: &RuntimeCallable) -> usize {
+        let name = f.fullname();
+        if let Some(idx) = self.by_name.get(&name) {
+            return *idx;
+        }
+        self.functions.push(FunctionStats {
+            name: name.clone(),
+            ..Default::default()
+        });
+        self.active_counts.push(0);
+        self.by_name.insert(name, self.functions.len() - 1);
+        self.functions.len() - 1
+    }
+
+    fn node_index(&mut self, parent: Option<usize>, function: usize) -> usize {
+        if let Some(idx) = self.node_index.get(&(parent, function)) {
+            return *idx;
+        }
+        self.nodes.push(StackNode {
+            parent,
+            function,
+            instructions: 0,
+        });
+        self.node_index
+            .insert((parent, function), self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    fn enter(&mut self, f: &RuntimeCallable, now: Instant) {
+        let function = self.function_index(f);
+        let parent = self.active.last().map(|a| a.node);
+        let node = self.node_index(parent, function);
+        let outermost = self.active_counts[function] == 0;
+        self.active_counts[function] += 1;
+        self.functions[function].calls += 1;
+        self.active.push(ActiveFrame {
+            function,
+            node,
+            entered: now,
+            child_time: Duration::ZERO,
+            instructions: 0,
+            child_instructions: 0,
+            outermost,
+        });
+    }
+
+    fn exit(&mut self, now: Instant) {
+        let Some(frame) = self.active.pop() else {
+            return;
+        };
+        self.active_counts[frame.function] -= 1;
+        let elapsed = now.duration_since(frame.entered);
+        let inclusive = frame.instructions + frame.child_instructions;
+
+        let stats = &mut self.functions[frame.function];
+        stats.instructions_exclusive += frame.instructions;
+        stats.time_exclusive += elapsed.saturating_sub(frame.child_time);
+        if frame.outermost {
+            stats.instructions_inclusive += inclusive;
+            stats.time_inclusive += elapsed;
+        }
+        self.nodes[frame.node].instructions += frame.instructions;
+
+        if let Some(parent) = self.active.last_mut() {
+            parent.child_time += elapsed;
+            parent.child_instructions += inclusive;
+        }
+    }
+
+    fn instruction(&mut self, offset: usize) {
+        if let Some(frame) = self.active.last_mut() {
+            frame.instructions += 1;
+            *self.functions[frame.function]
+                .offsets
+                .entry(offset)
+                .or_default() += 1;
+        }
+    }
+
+    // closes the frames still open, e.g. after the program failed
+    pub fn finish(&mut self) {
+        let now = Instant::now();
+        while !self.active.is_empty() {
+            self.exit(now);
+        }
+    }
+
+    pub fn functions(&self) -> &[FunctionStats] {
+        &self.functions
+    }
+
+    pub fn function(&self, fullname: &str) -> Option<&FunctionStats> {
+        self.by_name.get(fullname).map(|idx| &self.functions[*idx])
+    }
+
+    // groups instruction counts by the block labels recorded in the module
+    // definitions; a block's executions are the executions of its first
+    // instruction
+    pub fn blocks(&self, module_defs: &[ModuleDef]) -> Vec<BlockStats> {
+        let mut fdefs = HashMap::new();
+        for mdef in module_defs {
+            for fdef in mdef.functions() {
+                fdefs.insert(format!("{}.{}", mdef.name(), fdef.name()), fdef);
+            }
+        }
+
+        let mut blocks = vec![];
+        for stats in &self.functions {
+            if stats.offsets.is_empty() {
+                continue;
+            }
+            for (label, range) in block_ranges(fdefs.get(&stats.name).copied()) {
+                let instructions = stats
+                    .offsets
+                    .iter()
+                    .filter(|(o, _)| range.contains(o))
+                    .map(|(_, c)| *c)
+                    .sum();
+                if instructions == 0 {
+                    continue;
+                }
+                blocks.push(BlockStats {
+                    function: stats.name.clone(),
+                    label,
+                    offset: range.start,
+                    executions: stats.offsets.get(&range.start).cloned().unwrap_or_default(),
+                    instructions,
+                });
+            }
+        }
+        blocks
+    }
+
+    pub fn write_report<W: Write>(
+        &self,
+        module_defs: &[ModuleDef],
+        out: &mut W,
+    ) -> std::io::Result<()> {
+        let mut functions = self.functions.iter().collect::<Vec<_>>();
+        functions.sort_by(|a, b| {
+            b.instructions_inclusive
+                .cmp(&a.instructions_inclusive)
+                .then(a.name.cmp(&b.name))
+        });
+
+        writeln!(
+            out,
+            "{:<40} {:>10} {:>14} {:>14} {:>12} {:>12}",
+            "function", "calls", "instr incl", "instr excl", "us incl", "us excl"
+        )?;
+        for f in functions {
+            writeln!(
+                out,
+                "{:<40} {:>10} {:>14} {:>14} {:>12} {:>12}",
+                f.name,
+                f.calls,
+                f.instructions_inclusive,
+                f.instructions_exclusive,
+                f.time_inclusive.as_micros(),
+                f.time_exclusive.as_micros()
+            )?;
+        }
+
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{:<40} {:<16} {:>8} {:>12} {:>14}",
+            "function", "block", "offset", "executions", "instructions"
+        )?;
+        for b in self.blocks(module_defs) {
+            writeln!(
+                out,
+                "{:<40} {:<16} {:>8} {:>12} {:>14}",
+                b.function, b.label, b.offset, b.executions, b.instructions
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn folded_stacks(&self) -> Vec<(String, u64)> {
+        let mut stacks = vec![];
+        for node in &self.nodes {
+            if node.instructions == 0 {
+                continue;
+            }
+            let mut names = vec![self.functions[node.function].name.as_str()];
+            let mut parent = node.parent;
+            while let Some(p) = parent {
+                names.push(self.functions[self.nodes[p].function].name.as_str());
+                parent = self.nodes[p].parent;
+            }
+            names.reverse();
+            stacks.push((names.join(";"), node.instructions));
+        }
+        stacks.sort();
+        stacks
+    }
+
+    pub fn write_folded<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        for (stack, count) in self.folded_stacks() {
+            writeln!(out, "{stack} {count}")?;
+        }
+        Ok(())
+    }
+}
+
+pub struct ProfileObserver {
+    profile: Rc<RefCell<Profile>>,
+}
+
+impl ProfileObserver {
+    pub fn new(profile: &Rc<RefCell<Profile>>) -> Self {
+        Self {
+            profile: profile.clone(),
+        }
+    }
+}
+
+impl ExecutionObserver for ProfileObserver {
+    fn before_instruction(&mut self, event: &InstructionEvent) -> ObserverAction {
+        self.profile.borrow_mut().instruction(event.offset);
+        ObserverAction::Continue
+    }
+
+    fn function_entry(&mut self, f: &RuntimeCallable, _depth: usize) {
+        self.profile.borrow_mut().enter(f, Instant::now());
+    }
+
+    fn function_exit(&mut self, _f: &RuntimeCallable, _depth: usize) {
+        self.profile.borrow_mut().exit(Instant::now());
+    }
+}
@@ -0,0 +1,187 @@
+use std::io::{BufRead, Write};
+
+use assembler::assembler::assemble_module;
+use runtime::{environ::Environment, runloop::run_loop, runtime_module::RuntimeModule};
+
+use crate::module_source_for_path;
+
+const REPL_MODULE: &str = "repl";
+const EVAL_FUNCTION: &str = "repleval";
+
+// keeps one Environment across inputs; definitions typed so far live in the
+// "repl" module, which is reassembled whenever a definition is added, and any
+// other input runs as the body of a function in that module; each input gets
+// the full instruction budget
+pub struct Repl {
+    env: Environment,
+    definitions: Vec<String>,
+    make_env: Box<dyn Fn() -> Environment>,
+    instruction_budget: Option<u64>,
+}
+
+fn is_definition(line: &str) -> bool {
+    let first = line.split_whitespace().next().unwrap_or_default();
+    first.eq_ignore_ascii_case("fn") || first.eq_ignore_ascii_case("pub") || first.starts_with('%')
+}
+
+impl Repl {
+    pub fn new(make_env: Box<dyn Fn() -> Environment>) -> Self {
+        Self {
+            env: make_env(),
+            definitions: vec![],
+            make_env,
+            instruction_budget: None,
+        }
+    }
+
+    pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+        self.instruction_budget = budget;
+    }
+
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    fn module_source(&self, extra: &str) -> String {
+        let mut src = format!("@modname \"{REPL_MODULE}\"\n");
+        for d in &self.definitions {
+            src.push_str(d);
+            src.push('\n');
+        }
+        src.push_str(extra);
+        src
+    }
+
+    fn assemble(&mut self, extra: &str) -> Result<(), String> {
+        let mdef = assemble_module(&self.module_source(extra), None).map_err(|e| format!("{e}"))?;
+        self.env.add_module(RuntimeModule::from(&mdef));
+        Ok(())
+    }
+
+    pub fn define(&mut self, definition: &str) -> Result<(), String> {
+        self.definitions.push(definition.to_owned());
+        let assembled = self.assemble("");
+        if assembled.is_err() {
+            self.definitions.pop();
+        }
+        assembled
+    }
+
+    pub fn eval(&mut self, instructions: &str) -> Result<(), String> {
+        self.assemble(&format!(
+            "fn {EVAL_FUNCTION}\n  :entry\n{instructions}\n    ret\n"
+        ))?;
+        let f = self
+            .env
+            .lookup_function(&format!("{REPL_MODULE}.{EVAL_FUNCTION}"))
+            .ok_or("unable to find the evaluated function".to_owned())?;
+        self.env.set_instruction_budget(self.instruction_budget);
+        let depth = self.env.stack_len();
+        let result = run_loop(&f, &mut self.env);
+        if let Err(err) = result {
+            let unwind = self.env.print_unwind();
+            self.env.clear_frames();
+            // drop whatever the failed input left behind
+            while self.env.stack_len() > depth {
+                self.env.pop_value();
+            }
+            return Err(format!("{err}\n{unwind}"));
+        }
+        Ok(())
+    }
+
+    pub fn load(&mut self, path: &str) -> Result<Vec<String>, String> {
+        let source = module_source_for_path(path);
+        let bundle = source
+            .read_bundle()
+            .map_err(|err| format!("error trying to read {}: {}", source.description(), err))?;
+        Ok(bundle
+            .into_modules()
+            .iter()
+            .map(|mdef| {
+                self.env.add_module(RuntimeModule::from(mdef));
+                mdef.name().to_owned()
+            })
+            .collect())
+    }
+
+    pub fn reset(&mut self) {
+        self.env = (self.make_env)();
+        self.definitions.clear();
+    }
+
+    fn print_stack<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        for (i, v) in self.env.stack_values().iter().enumerate().rev() {
+            writeln!(out, "  {i}: {v}")?;
+        }
+        Ok(())
+    }
+
+    // a line starting a definition is continued until an empty line
+    pub fn run<R: BufRead, W: Write>(&mut self, mut input: R, out: &mut W) -> std::io::Result<()> {
+        loop {
+            write!(out, "tr> ")?;
+            out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = line.trim();
+
+            if let Some(command) = line.strip_prefix(':') {
+                let mut words = command.split_whitespace();
+                match (words.next(), words.next()) {
+                    (Some("q" | "quit"), None) => return Ok(()),
+                    (Some("stack"), None) => self.print_stack(out)?,
+                    (Some("modules"), None) => {
+                        for name in self.env.module_names() {
+                            writeln!(out, "  {name}")?;
+                        }
+                    }
+                    (Some("reset"), None) => self.reset(),
+                    (Some("load"), Some(path)) => match self.load(path) {
+                        Ok(names) => writeln!(out, "loaded {}", names.join(", "))?,
+                        Err(err) => writeln!(out, "error: {err}")?,
+                    },
+                    _ => writeln!(
+                        out,
+                        "commands: :load <file>, :stack, :modules, :reset, :quit"
+                    )?,
+                }
+                continue;
+            }
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if is_definition(line) {
+                let mut definition = line.to_owned();
+                if line
+                    .split_whitespace()
+                    .any(|w| w.eq_ignore_ascii_case("fn"))
+                {
+                    loop {
+                        write!(out, "... ")?;
+                        out.flush()?;
+                        let mut more = String::new();
+                        if input.read_line(&mut more)? == 0 || more.trim().is_empty() {
+                            break;
+                        }
+                        definition.push('\n');
+                        definition.push_str(more.trim_end());
+                    }
+                }
+                if let Err(err) = self.define(&definition) {
+                    writeln!(out, "error: {err}")?;
+                }
+                continue;
+            }
+
+            match self.eval(line) {
+                Ok(_) => self.print_stack(out)?,
+                Err(err) => writeln!(out, "error: {err}")?,
+            }
+        }
+    }
+}
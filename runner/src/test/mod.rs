@@ -0,0 +1,689 @@
+use runtime::{
+    builder::Builder, environ::Environment, instruction_def::InstructionDef,
+    intern_value::InternValue, module_definition::ModuleDef, module_loader::ModuleLoader,
+    runtime_module::RuntimeModule, values::RuntimeValue,
+};
+
+use crate::{
+    coverage_report::{coverage_report, write_lcov, write_summary, BlockCoverage},
+    debugger::{debug_run, parse_breakpoint, DebugOutcome},
+    profiler::{BlockStats, Profile, ProfileObserver},
+    tracer::TraceObserver,
+};
+
+fn add_twice_module() -> ModuleDef {
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(InternValue::Integer(0));
+    md.add_interned_value(InternValue::Integer(1));
+    md.add_call_target("module.add1");
+
+    let mut builder = Builder::new("add1");
+    let mut entry = builder.append_block("entry");
+    let mut done = builder.append_block("done");
+    entry.append_instruction(InstructionDef::PUSH(1));
+    entry.append_instruction(InstructionDef::JUMP(done.clone()));
+    done.append_instruction(InstructionDef::ADD);
+    done.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let mut builder = Builder::new("main");
+    let mut entry = builder.append_block("entry");
+    entry.append_instruction(InstructionDef::PUSH(0));
+    entry.append_instruction(InstructionDef::CALLDIRECT(0));
+    entry.append_instruction(InstructionDef::CALLDIRECT(0));
+    entry.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    md
+}
+
+fn debug_script(script: &str) -> (Environment, DebugOutcome, String) {
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&add_twice_module()));
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+
+    let mut out: Vec<u8> = vec![];
+    let outcome =
+        debug_run(&mut env, &main, script.as_bytes(), &mut out).expect("debugger i/o failed");
+    (
+        env,
+        outcome,
+        String::from_utf8(out).expect("invalid output"),
+    )
+}
+
+struct AsmLoader;
+
+impl ModuleLoader for AsmLoader {
+    fn load_module(&mut self, name: &str) -> Result<Option<ModuleDef>, String> {
+        if name != "asm" {
+            return Ok(None);
+        }
+        let mut mdef = ModuleDef::new("asm");
+        let mut builder = Builder::new("main");
+        builder
+            .append_block(":entry")
+            .append_instruction(InstructionDef::RET);
+        mdef.add_function(builder.generate());
+        Ok(Some(mdef))
+    }
+}
+
+#[test]
+fn test_parse_breakpoint() {
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&add_twice_module()));
+    assert_eq!(
+        Ok(("module.add1".to_owned(), 6)),
+        parse_breakpoint("module.add1:done", &env)
+    );
+    assert_eq!(
+        Ok(("module.main".to_owned(), 3)),
+        parse_breakpoint("module.main:3", &env)
+    );
+    assert!(parse_breakpoint("module.add1:nope", &env).is_err());
+    assert!(parse_breakpoint("asm.main:entry", &env).is_err());
+
+    // modules the program would load on demand are loaded to resolve labels
+    env.set_module_loader(Some(Box::new(AsmLoader)));
+    assert_eq!(
+        Ok(("asm.main".to_owned(), 0)),
+        parse_breakpoint("asm.main:entry", &env)
+    );
+    assert!(env.find_module("asm").is_some());
+    assert!(parse_breakpoint("other.add1:done", &env).is_err());
+    assert!(parse_breakpoint("module.add1", &env).is_err());
+}
+
+#[test]
+fn test_debugger_session() {
+    let script = "b module.add1:done\nc\nbt\nf\nn\nd module.add1:done\nc\n";
+    let (mut env, outcome, out) = debug_script(script);
+    assert!(matches!(outcome, DebugOutcome::Finished(Ok(()))));
+    assert_eq!(RuntimeValue::Integer(2), env.pop_value());
+    assert!(env.is_stack_empty());
+
+    let stops = out
+        .lines()
+        .filter_map(|l| l.strip_prefix("(tdb) stopped at "))
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        vec![
+            "module.add1:6 (breakpoint): ADD",
+            "module.main:6: CALLDIRECT(0)",
+            "module.add1:6 (breakpoint): ADD",
+        ],
+        stops
+    );
+    assert!(out.starts_with("stopped at module.main:0: PUSH(0)\nstack: []\n"));
+    assert!(out.contains(
+        "stack: [Integer(0), Integer(1)]\nslots: []\nframes:\n  module.add1:6\n  module.main:3\n"
+    ));
+    assert!(out.contains("breakpoint removed at module.add1:6"));
+}
+
+#[test]
+fn test_debugger_step_over() {
+    let (env, outcome, out) = debug_script("n\nn\ns\ns\nq\n");
+    assert!(matches!(outcome, DebugOutcome::Quit));
+    assert_eq!(0, env.call_depth());
+
+    let stops = out
+        .lines()
+        .filter_map(|l| l.strip_prefix("(tdb) stopped at "))
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        vec![
+            "module.main:3: CALLDIRECT(0)",
+            "module.main:6: CALLDIRECT(0)",
+            "module.add1:0: PUSH(1)",
+            "module.add1:3: JUMP(6)",
+        ],
+        stops
+    );
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn trace_add_twice(top: usize) -> Vec<serde_json::Value> {
+    let buffer = SharedBuffer::default();
+    let mut env = Environment::default();
+    env.add_observer(Box::new(TraceObserver::new(buffer.clone(), top)));
+    env.add_module(RuntimeModule::from(&add_twice_module()));
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    assert!(runtime::runloop::run_loop(&main, &mut env).is_ok());
+
+    let out = String::from_utf8(buffer.0.borrow().clone()).expect("invalid output");
+    out.lines()
+        .map(|l| serde_json::from_str(l).expect("invalid trace record"))
+        .collect()
+}
+
+#[test]
+fn test_trace() {
+    let records = trace_add_twice(0);
+    assert_eq!(12, records.len());
+    assert_eq!(
+        serde_json::json!({
+            "function": "module.main",
+            "ip": 0,
+            "instruction": "PUSH(0)",
+            "stack_depth": 0,
+            "call_depth": 1
+        }),
+        records[0]
+    );
+    assert_eq!(
+        serde_json::json!({
+            "function": "module.add1",
+            "ip": 6,
+            "instruction": "ADD",
+            "stack_depth": 2,
+            "call_depth": 2
+        }),
+        records[4]
+    );
+    assert_eq!("module.main", records[11]["function"]);
+    assert_eq!("RET", records[11]["instruction"]);
+}
+
+#[test]
+fn test_trace_top_values() {
+    let records = trace_add_twice(1);
+    assert_eq!(serde_json::json!([]), records[0]["top"]);
+    assert_eq!(serde_json::json!(["Integer(1)"]), records[4]["top"]);
+
+    let records = trace_add_twice(5);
+    assert_eq!(
+        serde_json::json!(["Integer(1)", "Integer(0)"]),
+        records[4]["top"]
+    );
+}
+
+fn recursive_module(n: u64) -> ModuleDef {
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(InternValue::Integer(0));
+    md.add_interned_value(InternValue::Integer(1));
+    md.add_interned_value(InternValue::Integer(n));
+    md.add_call_target("module.down");
+
+    let mut builder = Builder::new("down");
+    let mut entry = builder.append_block("entry");
+    let mut done = builder.append_block("done");
+    entry.append_instruction(InstructionDef::DUP);
+    entry.append_instruction(InstructionDef::PUSH(0));
+    entry.append_instruction(InstructionDef::EQ);
+    entry.append_instruction(InstructionDef::JTRUE(done.clone()));
+    entry.append_instruction(InstructionDef::PUSH(1));
+    entry.append_instruction(InstructionDef::SWAP);
+    entry.append_instruction(InstructionDef::SUB);
+    entry.append_instruction(InstructionDef::CALLDIRECT(0));
+    entry.append_instruction(InstructionDef::RET);
+    done.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let mut builder = Builder::new("main");
+    let mut entry = builder.append_block("entry");
+    entry.append_instruction(InstructionDef::PUSH(2));
+    entry.append_instruction(InstructionDef::CALLDIRECT(0));
+    entry.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    md
+}
+
+fn profile_module(md: &ModuleDef) -> Profile {
+    let profile = std::rc::Rc::new(std::cell::RefCell::new(Profile::default()));
+    let mut env = Environment::default();
+    env.add_observer(Box::new(ProfileObserver::new(&profile)));
+    env.add_module(RuntimeModule::from(md));
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    assert!(runtime::runloop::run_loop(&main, &mut env).is_ok());
+    profile.borrow_mut().finish();
+    profile.take()
+}
+
+#[test]
+fn test_profile_functions() {
+    let md = add_twice_module();
+    let profile = profile_module(&md);
+
+    let main = profile.function("module.main").unwrap();
+    assert_eq!(1, main.calls);
+    assert_eq!(12, main.instructions_inclusive);
+    assert_eq!(4, main.instructions_exclusive);
+    assert!(main.time_inclusive >= main.time_exclusive);
+
+    let add1 = profile.function("module.add1").unwrap();
+    assert_eq!(2, add1.calls);
+    assert_eq!(8, add1.instructions_inclusive);
+    assert_eq!(8, add1.instructions_exclusive);
+    assert_eq!(Some(&2), add1.offsets.get(&6));
+
+    let mut blocks = profile.blocks(&[md]);
+    blocks.sort_by(|a, b| (&a.function, a.offset).cmp(&(&b.function, b.offset)));
+    assert_eq!(
+        vec![
+            BlockStats {
+                function: "module.add1".to_owned(),
+                label: "entry".to_owned(),
+                offset: 0,
+                executions: 2,
+                instructions: 4,
+            },
+            BlockStats {
+                function: "module.add1".to_owned(),
+                label: "done".to_owned(),
+                offset: 6,
+                executions: 2,
+                instructions: 4,
+            },
+            BlockStats {
+                function: "module.main".to_owned(),
+                label: "entry".to_owned(),
+                offset: 0,
+                executions: 1,
+                instructions: 4,
+            },
+        ],
+        blocks
+    );
+
+    let mut folded: Vec<u8> = vec![];
+    profile.write_folded(&mut folded).unwrap();
+    assert_eq!(
+        "module.main 4\nmodule.main;module.add1 8\n",
+        String::from_utf8(folded).unwrap()
+    );
+
+    let mut report: Vec<u8> = vec![];
+    profile
+        .write_report(&[add_twice_module()], &mut report)
+        .unwrap();
+    let report = String::from_utf8(report).unwrap();
+    assert!(report.starts_with("function "));
+    assert!(report.lines().nth(1).unwrap().starts_with("module.main "));
+}
+
+#[test]
+fn test_profile_recursion() {
+    let profile = profile_module(&recursive_module(2));
+
+    let down = profile.function("module.down").unwrap();
+    assert_eq!(3, down.calls);
+    assert_eq!(23, down.instructions_inclusive);
+    assert_eq!(23, down.instructions_exclusive);
+    let main = profile.function("module.main").unwrap();
+    assert_eq!(26, main.instructions_inclusive);
+
+    assert_eq!(
+        vec![
+            ("module.main".to_owned(), 3),
+            ("module.main;module.down".to_owned(), 9),
+            ("module.main;module.down;module.down".to_owned(), 9),
+            (
+                "module.main;module.down;module.down;module.down".to_owned(),
+                5
+            ),
+        ],
+        profile.folded_stacks()
+    );
+}
+
+fn add_once_module_with_lines() -> ModuleDef {
+    let mut md = ModuleDef::new("module");
+    md.set_source("module.tas");
+    md.add_interned_value(InternValue::Integer(0));
+    md.add_interned_value(InternValue::Integer(1));
+    md.add_call_target("module.add1");
+
+    let mut builder = Builder::new("add1");
+    let mut entry = builder.append_block(":entry");
+    let mut done = builder.append_block(":done");
+    let mut unused = builder.append_block(":unused");
+    entry.append_instruction_at_line(InstructionDef::PUSH(1), 3);
+    entry.append_instruction_at_line(InstructionDef::JUMP(done.clone()), 4);
+    done.append_instruction_at_line(InstructionDef::ADD, 6);
+    done.append_instruction_at_line(InstructionDef::RET, 7);
+    unused.append_instruction_at_line(InstructionDef::RET, 9);
+    md.add_function(builder.generate());
+
+    let mut builder = Builder::new("main");
+    let mut entry = builder.append_block(":entry");
+    entry.append_instruction_at_line(InstructionDef::PUSH(0), 12);
+    entry.append_instruction_at_line(InstructionDef::CALLDIRECT(0), 13);
+    entry.append_instruction_at_line(InstructionDef::RET, 14);
+    md.add_function(builder.generate());
+
+    md
+}
+
+#[test]
+fn test_coverage_report() {
+    let md = add_once_module_with_lines();
+    let coverage = std::rc::Rc::new(std::cell::RefCell::new(
+        runtime::coverage::Coverage::default(),
+    ));
+    let mut env = Environment::default();
+    env.add_observer(Box::new(runtime::coverage::CoverageObserver::new(
+        &coverage,
+    )));
+    env.add_module(RuntimeModule::from(&md));
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    assert!(runtime::runloop::run_loop(&main, &mut env).is_ok());
+
+    let report = coverage_report(&coverage.borrow(), &[md]);
+    assert_eq!(1, report.len());
+    assert_eq!("module.tas", report[0].source);
+    let add1 = &report[0].functions[0];
+    assert_eq!("module.add1", add1.name);
+    assert_eq!(Some(3), add1.line);
+    assert_eq!(1, add1.hits);
+    assert_eq!(
+        BlockCoverage {
+            label: ":unused".to_owned(),
+            offset: 8,
+            line: Some(9),
+            instructions: 1,
+            covered: 0,
+            hits: 0,
+        },
+        add1.blocks[2]
+    );
+
+    let mut lcov: Vec<u8> = vec![];
+    write_lcov(&report, &mut lcov).unwrap();
+    assert_eq!(
+        "TN:\nSF:module.tas\nFN:3,module.add1\nFN:12,module.main\n\
+         FNDA:1,module.add1\nFNDA:1,module.main\nFNF:2\nFNH:2\n\
+         DA:3,1\nDA:4,1\nDA:6,1\nDA:7,1\nDA:9,0\nDA:12,1\nDA:13,1\nDA:14,1\n\
+         LF:8\nLH:7\nend_of_record\n",
+        String::from_utf8(lcov).unwrap()
+    );
+
+    let mut summary: Vec<u8> = vec![];
+    write_summary(&report, &mut summary).unwrap();
+    assert_eq!(
+        "module (module.tas): lines 7/8 (87.5%), blocks 3/4 (75.0%)\n\
+         \x20 module.add1: instructions 4/5 (80.0%)\n\
+         \x20   block :unused at offset 8, line 9: 0/1 instructions\n\
+         \x20 module.main: instructions 3/3 (100.0%)\n",
+        String::from_utf8(summary).unwrap()
+    );
+}
+
+#[test]
+fn test_exit_code() {
+    use crate::{exit_code, EXIT_LINK_ERROR, EXIT_RUNTIME_ERROR};
+    use runtime::runloop::{run_loop, RunloopErrData, RunloopError};
+
+    let mdefs = [add_twice_module()];
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&mdefs[0]));
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    let result = run_loop(&main, &mut env);
+    assert_eq!(2, exit_code(&result, &env));
+
+    env.push_value(RuntimeValue::Logical(true));
+    assert_eq!(0, exit_code(&Ok(()), &env));
+    env.push_value(RuntimeValue::Integer(255));
+    assert_eq!(255, exit_code(&Ok(()), &env));
+    env.push_value(RuntimeValue::Integer(256));
+    assert_eq!(255, exit_code(&Ok(()), &env));
+    env.push_value(RuntimeValue::Integer(u64::MAX));
+    assert_eq!(255, exit_code(&Ok(()), &env));
+
+    let missing = Err(RunloopError {
+        cur_ptr: 0,
+        instruction: None,
+        data: RunloopErrData::MissingFunction("module.nope".to_owned()),
+    });
+    assert_eq!(EXIT_LINK_ERROR, exit_code(&missing, &env));
+    let empty = Err(RunloopError {
+        cur_ptr: 4,
+        instruction: None,
+        data: RunloopErrData::EmptyStack,
+    });
+    assert_eq!(EXIT_RUNTIME_ERROR, exit_code(&empty, &env));
+}
+
+#[test]
+fn test_module_source_for_path() {
+    use crate::module_source_for_path;
+
+    let dir = std::env::temp_dir().join(format!("runner-sources-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("unable to create temp dir");
+    let src = r#"
+@modname "com.tukunc.sources"
+fn main
+  :entry
+    lpush 3
+    ret
+"#;
+    let text = dir.join("main.tas");
+    std::fs::write(&text, src).expect("unable to write source");
+    let binary = dir.join("main.out");
+    let bytes = assembler::assembler::do_assemble(src, None).expect("invalid source");
+    std::fs::write(&binary, bytes).expect("unable to write bytecode");
+    let untyped = dir.join("main");
+    std::fs::write(&untyped, src).expect("unable to write source");
+
+    for path in [&text, &binary, &untyped] {
+        let mdef = module_source_for_path(path.to_str().unwrap())
+            .read()
+            .expect("unable to read module");
+        assert_eq!("com.tukunc.sources", mdef.name());
+    }
+
+    let broken = dir.join("broken.tas");
+    std::fs::write(&broken, "fn main\n  ret\n").expect("unable to write source");
+    let err = module_source_for_path(broken.to_str().unwrap())
+        .read()
+        .expect_err("broken source assembled");
+    assert!(format!("{err}").starts_with("parse error"));
+
+    let undefined = dir.join("undefined.tas");
+    std::fs::write(&undefined, "fn main\n  :entry\n    push \"zz\"\n    ret\n")
+        .expect("unable to write source");
+    let err = module_source_for_path(undefined.to_str().unwrap())
+        .read()
+        .expect_err("undefined constant assembled");
+    assert_eq!("lowering error: invalid const name zz", format!("{err}"));
+
+    std::fs::remove_dir_all(&dir).expect("unable to remove temp dir");
+}
+
+#[test]
+fn test_module_path_loader() {
+    use crate::module_path::{ModulePath, ModulePathLoader};
+    use runtime::runloop::{run_loop, RunloopErrData};
+
+    let dir = std::env::temp_dir().join(format!("runner-module-path-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("com/foo")).expect("unable to create temp dir");
+    std::fs::write(
+        dir.join("com/foo/bar.tas"),
+        r#"
+@modname "com.foo.bar"
+pub fn seven
+  :entry
+    lpush 7
+    ret
+"#,
+    )
+    .expect("unable to write source");
+    std::fs::write(dir.join("com/foo/baz.tas"), "@modname \"com.foo.other\"\n")
+        .expect("unable to write source");
+
+    let mut path = ModulePath::default();
+    path.add_dir(dir.join("missing"));
+    path.add_dir(&dir);
+    assert_eq!(
+        Some(dir.join("com/foo/bar.tas")),
+        path.resolve("com.foo.bar")
+    );
+    assert_eq!(None, path.resolve("com.foo.nope"));
+    for name in [
+        "",
+        "com..foo.bar",
+        "com.foo.bar.",
+        "com/foo.bar",
+        "com\\foo.bar",
+        "/com.foo.bar",
+        "...com.foo.bar",
+    ] {
+        assert_eq!(None, path.resolve(name), "{name}");
+    }
+
+    let main = assembler::assembler::assemble_module(
+        r#"
+@modname "app"
+fn main
+  :entry
+    fcall "com.foo.bar.seven"
+    ret
+"#,
+        None,
+    )
+    .expect("invalid source");
+
+    let loader = ModulePathLoader::new(path);
+    let loaded = loader.loaded();
+    let mut env = Environment::default();
+    env.set_module_loader(Some(Box::new(loader)));
+    env.add_module(RuntimeModule::from(&main));
+    assert!(env.find_module("com.foo.bar").is_none());
+
+    let f = env
+        .lookup_function("app.main")
+        .expect("main function missing");
+    assert!(run_loop(&f, &mut env).is_ok());
+    assert_eq!(RuntimeValue::Integer(7), env.pop_value());
+    assert!(env.find_module("com.foo.bar").is_some());
+    assert_eq!(1, loaded.borrow().len());
+
+    // a file that defines another module does not satisfy the lookup, and
+    // the loader's error is reported rather than a missing function
+    assert!(env.lookup_function("com.foo.baz.f").is_none());
+    assert!(env.find_module("com.foo.other").is_none());
+    let err = env.call("com.foo.baz.f", &[]).unwrap_err();
+    assert!(matches!(
+        &err.data,
+        RunloopErrData::ModuleLoadFailed(m, msg) if m == "com.foo.baz" && msg.contains("com.foo.other")
+    ));
+    assert_eq!(crate::EXIT_LOAD_ERROR, crate::exit_code(&Err(err), &env));
+    assert!(env.lookup_function("com.foo.nope.f").is_none());
+
+    std::fs::remove_dir_all(&dir).expect("unable to remove temp dir");
+}
+
+#[test]
+fn test_bundle_module_source() {
+    use crate::module_source_for_path;
+
+    let dir = std::env::temp_dir().join(format!("runner-bundle-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("unable to create temp dir");
+    let mut second = ModuleDef::new("second");
+    second.add_function(runtime::builder::Builder::new("f").generate());
+    let bundle = assembler::bundle::make_bundle(vec![add_twice_module(), second], None)
+        .expect("bundle failed");
+    let bytes = bundle.to_bytes().expect("serialization failed");
+
+    // the header is recognized without the bundle extension
+    for name in ["app.tbn", "app"] {
+        let path = dir.join(name);
+        std::fs::write(&path, &bytes).expect("unable to write bundle");
+        let source = module_source_for_path(path.to_str().unwrap());
+        let read = source.read_bundle().expect("unable to read bundle");
+        assert_eq!(None, read.entry());
+        assert_eq!(
+            vec!["module", "second"],
+            read.modules().map(|m| m.name()).collect::<Vec<&str>>()
+        );
+        assert!(source.read().is_err());
+    }
+
+    std::fs::remove_dir_all(&dir).expect("unable to remove temp dir");
+}
+
+#[test]
+fn test_repl_session() {
+    use crate::repl::Repl;
+
+    let mut repl = Repl::new(Box::new(|| {
+        let mut env = Environment::default();
+        corelib::register_corelib(&mut env);
+        env
+    }));
+    let script = r#"lpush 2 lpush 3
+add
+fn double
+  :entry
+    dup
+    add
+    ret
+
+fcall "repl.double"
+pop
+:stack
+:modules
+fn broken
+
+bogus
+:reset
+:stack
+lpush 1
+"#;
+    let mut out: Vec<u8> = vec![];
+    repl.run(script.as_bytes(), &mut out)
+        .expect("repl i/o failed");
+    let out = String::from_utf8(out).expect("invalid output");
+
+    assert!(out.starts_with("tr>   1: Integer(3)\n  0: Integer(2)\ntr>   0: Integer(5)\n"));
+    assert!(out.contains("tr> ... ... ... ... ... tr>   0: Integer(10)\n"));
+    assert!(out.contains("  corelib\n  repl\n"));
+    assert_eq!(2, out.matches("error: parse error").count());
+    assert!(out.ends_with("tr> tr> tr>   0: Integer(1)\ntr> "));
+    assert_eq!(1, repl.env().stack_len());
+}
+
+#[test]
+fn test_repl_recovers_from_errors() {
+    use crate::repl::Repl;
+
+    let mut repl = Repl::new(Box::new(Environment::default));
+    repl.set_instruction_budget(Some(5));
+    assert!(repl.eval("lpush 1").is_ok());
+    let err = repl.eval("push \"zz\"").unwrap_err();
+    assert_eq!("lowering error: invalid const name zz", err);
+    // running out of budget leaves no partial values behind, and every input
+    // starts with the full budget again
+    assert!(repl
+        .eval("lpush 2 lpush 3 lpush 4 lpush 5 lpush 6")
+        .is_err());
+    assert_eq!(&[RuntimeValue::Integer(1)], repl.env().stack_values());
+    for _ in 0..3 {
+        assert!(repl.eval("lpush 2 lpush 3 pop pop").is_ok());
+    }
+    assert_eq!(&[RuntimeValue::Integer(1)], repl.env().stack_values());
+}
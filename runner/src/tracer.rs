@@ -0,0 +1,73 @@
+use std::io::Write;
+
+use runtime::observer::{ExecutionObserver, InstructionEvent, ObserverAction};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct TraceRecord {
+    function: String,
+    ip: usize,
+    instruction: String,
+    stack_depth: usize,
+    call_depth: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top: Option<Vec<String>>,
+}
+
+// writes one JSON object per executed instruction; `top` is the number of
+// values from the top of the value stack to include, topmost first
+pub struct TraceObserver<W: Write> {
+    out: W,
+    top: usize,
+    failed: bool,
+}
+
+impl<W: Write> TraceObserver<W> {
+    pub fn new(out: W, top: usize) -> Self {
+        Self {
+            out,
+            top,
+            failed: false,
+        }
+    }
+
+    fn write_record(&mut self, record: &TraceRecord) -> std::io::Result<()> {
+        serde_json::to_writer(&mut self.out, record)?;
+        self.out.write_all(b"\n")
+    }
+}
+
+impl<W: Write> ExecutionObserver for TraceObserver<W> {
+    fn before_instruction(&mut self, event: &InstructionEvent) -> ObserverAction {
+        if self.failed {
+            return ObserverAction::Continue;
+        }
+
+        let top = if self.top > 0 {
+            Some(
+                event
+                    .stack
+                    .iter()
+                    .rev()
+                    .take(self.top)
+                    .map(|v| format!("{v}"))
+                    .collect(),
+            )
+        } else {
+            None
+        };
+        let record = TraceRecord {
+            function: event.function.fullname(),
+            ip: event.offset,
+            instruction: format!("{:?}", event.instruction),
+            stack_depth: event.stack.len(),
+            call_depth: event.depth,
+            top,
+        };
+        if let Err(err) = self.write_record(&record) {
+            eprintln!("error writing trace: {err}");
+            self.failed = true;
+        }
+        ObserverAction::Continue
+    }
+}
@@ -0,0 +1,86 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use runtime::{
+    builder::Builder,
+    environ::Environment,
+    instruction_def::InstructionDef,
+    intern_value::InternValue,
+    module_definition::ModuleDef,
+    runloop::run_loop,
+    runtime_module::{RuntimeCallable, RuntimeModule},
+};
+
+const ITERATIONS: u64 = 10_000;
+
+// counts slot 0 down from ITERATIONS to zero, calling "module.step"
+// on every iteration when with_call is set
+fn countdown_module(with_call: bool) -> ModuleDef {
+    let mut md = ModuleDef::new("module");
+    let count = md.add_interned_value(InternValue::Integer(ITERATIONS)) as u16;
+    let one = md.add_interned_value(InternValue::Integer(1)) as u16;
+    let zero = md.add_interned_value(InternValue::Integer(0)) as u16;
+    let step = md.add_call_target("module.step") as u16;
+
+    let mut builder = Builder::new("main");
+    let mut entry = builder.append_block("entry");
+    let mut body = builder.append_block("loop");
+    let mut exit = builder.append_block("exit");
+
+    entry.append_instruction(InstructionDef::PUSH(count));
+    entry.append_instruction(InstructionDef::TOSLOT(0));
+    entry.append_instruction(InstructionDef::JUMP(body.clone()));
+
+    body.append_instruction(InstructionDef::PUSH(one));
+    body.append_instruction(InstructionDef::FROMSLOT(0));
+    if with_call {
+        body.append_instruction(InstructionDef::CALLDIRECT(step));
+    } else {
+        body.append_instruction(InstructionDef::SUB);
+    }
+    body.append_instruction(InstructionDef::DUP);
+    body.append_instruction(InstructionDef::TOSLOT(0));
+    body.append_instruction(InstructionDef::PUSH(zero));
+    body.append_instruction(InstructionDef::EQ);
+    body.append_instruction(InstructionDef::JTRUE(exit.clone()));
+    body.append_instruction(InstructionDef::JUMP(body.clone()));
+
+    exit.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let mut builder = Builder::new("step");
+    let mut entry = builder.append_block("entry");
+    entry.append_instruction(InstructionDef::SUB);
+    entry.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    md
+}
+
+fn setup(with_call: bool) -> (Environment, RuntimeCallable) {
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&countdown_module(with_call)));
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    (env, main)
+}
+
+fn bench_loop(c: &mut Criterion) {
+    let (mut env, main) = setup(false);
+    c.bench_function("countdown loop", |b| {
+        b.iter(|| {
+            run_loop(black_box(&main), &mut env).expect("runloop failed");
+        })
+    });
+}
+
+fn bench_call_loop(c: &mut Criterion) {
+    let (mut env, main) = setup(true);
+    c.bench_function("countdown loop with calls", |b| {
+        b.iter(|| {
+            run_loop(black_box(&main), &mut env).expect("runloop failed");
+        })
+    });
+}
+
+criterion_group!(benches, bench_loop, bench_call_loop);
+criterion_main!(benches);
@@ -6,6 +6,7 @@ use crate::{bytecode::Bytecode, instruction_def::InstructionDef, module_definiti
 struct BasicBlockImpl {
     name: String,
     content: Vec<InstructionDef>,
+    lines: Vec<Option<u32>>,
     offset: usize,
 }
 
@@ -20,16 +21,29 @@ impl BasicBlock {
             val: Rc::new(RefCell::new(BasicBlockImpl {
                 name: name.to_owned(),
                 content: vec![],
+                lines: vec![],
                 offset: 0,
             })),
         }
     }
 
-    pub fn append_instruction(&mut self, i: InstructionDef) -> &mut Self {
-        self.val.borrow_mut().content.push(i);
+    fn push_instruction(&mut self, i: InstructionDef, line: Option<u32>) -> &mut Self {
+        {
+            let mut content = self.val.borrow_mut();
+            content.content.push(i);
+            content.lines.push(line);
+        }
         self
     }
 
+    pub fn append_instruction(&mut self, i: InstructionDef) -> &mut Self {
+        self.push_instruction(i, None)
+    }
+
+    pub fn append_instruction_at_line(&mut self, i: InstructionDef, line: u32) -> &mut Self {
+        self.push_instruction(i, Some(line))
+    }
+
     pub fn name(&self) -> String {
         self.val.as_ref().borrow().name.clone()
     }
@@ -60,6 +74,18 @@ impl BasicBlock {
         }
     }
 
+    fn add_debug_info(&self, fdef: &mut FunctionDef) {
+        let content = self.val.as_ref().borrow();
+        fdef.add_label(&content.name, content.offset);
+        let mut offset = content.offset;
+        for (i, line) in std::iter::zip(&content.content, &content.lines) {
+            if let Some(line) = line {
+                fdef.add_line(offset, *line);
+            }
+            offset += i.runtime_size();
+        }
+    }
+
     pub fn is_terminated(&self) -> bool {
         let content = self.val.as_ref().borrow();
         for i in &content.content {
@@ -125,6 +151,10 @@ impl Builder {
             i += 1;
         }
 
-        FunctionDef::new(&self.name, bc)
+        let mut fdef = FunctionDef::new(&self.name, bc);
+        for block in &self.blocks {
+            block.add_debug_info(&mut fdef);
+        }
+        fdef
     }
 }
@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+use crate::module_definition::{check_header, write_header, ModuleDef};
+
+// a serialized bundle starts with these bytes and the module format version,
+// since it embeds ModuleDefs, followed by the bincode of the Bundle
+pub const BUNDLE_MAGIC: &[u8; 8] = b"TUKUNBDL";
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub entry: Option<String>,
+}
+
+// several modules shipped together, with a manifest naming the function a
+// runner starts from
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Bundle {
+    manifest: BundleManifest,
+    modules: Vec<ModuleDef>,
+}
+
+impl Bundle {
+    pub fn new(entry: Option<&str>) -> Self {
+        Self {
+            manifest: BundleManifest {
+                entry: entry.map(str::to_owned),
+            },
+            modules: vec![],
+        }
+    }
+
+    pub fn add_module(&mut self, mdef: ModuleDef) {
+        self.modules.push(mdef);
+    }
+
+    pub fn manifest(&self) -> &BundleManifest {
+        &self.manifest
+    }
+
+    pub fn entry(&self) -> Option<&str> {
+        self.manifest.entry.as_deref()
+    }
+
+    pub fn set_entry(&mut self, entry: Option<&str>) {
+        self.manifest.entry = entry.map(str::to_owned);
+    }
+
+    pub fn modules(&self) -> std::slice::Iter<'_, ModuleDef> {
+        self.modules.iter()
+    }
+
+    pub fn into_modules(self) -> Vec<ModuleDef> {
+        self.modules
+    }
+
+    // true if a module of the bundle defines the "module.function" name
+    pub fn defines_function(&self, fullname: &str) -> bool {
+        let Some((module, function)) = fullname.rsplit_once('.') else {
+            return false;
+        };
+        self.modules
+            .iter()
+            .filter(|m| m.name() == module)
+            .any(|m| m.functions().any(|f| f.name() == function))
+    }
+
+    pub fn is_bundle(bytes: &[u8]) -> bool {
+        bytes.starts_with(BUNDLE_MAGIC)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = write_header(BUNDLE_MAGIC);
+        bincode::serialize_into(&mut bytes, self).map_err(|err| format!("{err}"))?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let body = check_header(bytes, BUNDLE_MAGIC, "bundle")?;
+        bincode::deserialize(body).map_err(|err| format!("{err}"))
+    }
+}
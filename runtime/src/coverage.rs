@@ -0,0 +1,61 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::{
+    observer::{ExecutionObserver, InstructionEvent, ObserverAction},
+    runtime_module::RuntimeCallable,
+};
+
+// execution counts per instruction offset, keyed by function fullname
+#[derive(Default, Debug)]
+pub struct Coverage {
+    functions: HashMap<String, HashMap<usize, u64>>,
+}
+
+impl Coverage {
+    pub fn hits(&self, fullname: &str) -> Option<&HashMap<usize, u64>> {
+        self.functions.get(fullname)
+    }
+
+    pub fn count(&self, fullname: &str, offset: usize) -> u64 {
+        self.hits(fullname)
+            .and_then(|h| h.get(&offset))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn functions(&self) -> impl Iterator<Item = &String> {
+        self.functions.keys()
+    }
+}
+
+pub struct CoverageObserver {
+    coverage: Rc<RefCell<Coverage>>,
+    last: Option<(RuntimeCallable, String)>,
+}
+
+impl CoverageObserver {
+    pub fn new(coverage: &Rc<RefCell<Coverage>>) -> Self {
+        Self {
+            coverage: coverage.clone(),
+            last: None,
+        }
+    }
+}
+
+impl ExecutionObserver for CoverageObserver {
+    fn before_instruction(&mut self, event: &InstructionEvent) -> ObserverAction {
+        let same = matches!(&self.last, Some((f, _)) if Rc::ptr_eq(&f.f, &event.function.f));
+        if !same {
+            self.last = Some((event.function.clone(), event.function.fullname()));
+        }
+        if let Some((_, name)) = &self.last {
+            let mut coverage = self.coverage.borrow_mut();
+            let hits = match coverage.functions.get_mut(name) {
+                Some(hits) => hits,
+                None => coverage.functions.entry(name.clone()).or_default(),
+            };
+            *hits.entry(event.offset).or_default() += 1;
+        }
+        ObserverAction::Continue
+    }
+}
@@ -1,17 +1,83 @@
-use std::collections::HashMap;
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use crate::{
+    limits::{ElementCounter, Limits},
+    log_info,
+    module_loader::{ModuleLoader, LOG_LOADER},
+    observer::{ExecutionObserver, ObserverId},
+    runloop::{run_loop, RunloopErrData, RunloopError},
     runtime_module::{RuntimeCallable, RuntimeModule, RuntimeTypeDef},
     stack::Stack,
+    types::RuntimeType,
     unwinder::Unwinder,
-    values::RuntimeValue,
+    values::{array::Array, RuntimeValue},
 };
 
-#[derive(Default)]
+#[derive(Debug, PartialEq, Eq)]
+pub enum LookupError {
+    Missing,
+    NotExported,
+    // the module loader failed for the named module
+    LoadFailed(String, String),
+}
+
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10000;
+
 pub struct Environment {
     pub(crate) runtime_stack: Stack<RuntimeValue>,
-    pub(crate) modules: HashMap<String, RuntimeModule>,
+    // lookups load modules on demand, so they only need a shared reference
+    pub(crate) modules: RefCell<HashMap<String, RuntimeModule>>,
     pub(crate) unwinder: Unwinder,
+    pub(crate) max_call_depth: usize,
+    pub(crate) instruction_budget: Option<u64>,
+    pub(crate) suspended_base: Option<usize>,
+    // run_frames calls in progress; more than one means a native re-entered
+    // the runloop
+    pub(crate) active_runs: usize,
+    pub(crate) limits: Limits,
+    pub(crate) live_elements: ElementCounter,
+    pub(crate) observers: Vec<(ObserverId, Box<dyn ExecutionObserver>)>,
+    pub(crate) next_observer: ObserverId,
+    pub(crate) skip_observers: bool,
+    pub(crate) args: Vec<String>,
+    pub(crate) module_loader: RefCell<Option<Box<dyn ModuleLoader>>>,
+    pub(crate) host_data: HashMap<TypeId, Box<dyn Any>>,
+    // modules cache their resolved CALLDIRECT targets for one generation;
+    // every Environment starts a new one, and so does replacing a module
+    pub(crate) targets_generation: u64,
+}
+
+fn next_targets_generation() -> u64 {
+    static GENERATION: AtomicU64 = AtomicU64::new(1);
+    GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            runtime_stack: Default::default(),
+            modules: Default::default(),
+            unwinder: Default::default(),
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            instruction_budget: None,
+            suspended_base: None,
+            active_runs: 0,
+            limits: Default::default(),
+            live_elements: Default::default(),
+            observers: vec![],
+            next_observer: Default::default(),
+            skip_observers: false,
+            args: vec![],
+            module_loader: RefCell::new(None),
+            host_data: HashMap::new(),
+            targets_generation: next_targets_generation(),
+        }
+    }
 }
 
 impl Environment {
@@ -23,6 +89,10 @@ impl Environment {
         self.runtime_stack.len()
     }
 
+    pub fn stack_values(&self) -> &[RuntimeValue] {
+        &self.runtime_stack.values
+    }
+
     pub fn is_stack_empty(&self) -> bool {
         self.runtime_stack.is_empty()
     }
@@ -31,41 +101,262 @@ impl Environment {
         self.runtime_stack.pop()
     }
 
+    // replacing a module may change what any call target resolves to
     pub fn add_module(&mut self, m: RuntimeModule) -> bool {
-        self.modules.insert(m.name().to_string(), m).is_none()
+        self.targets_generation = next_targets_generation();
+        self.modules
+            .get_mut()
+            .insert(m.name().to_string(), m)
+            .is_none()
     }
 
     pub fn find_module(&self, name: &str) -> Option<RuntimeModule> {
-        self.modules.get(name).cloned()
+        self.modules.borrow().get(name).cloned()
     }
 
-    fn lookup_module_dotted(&self, name: &str) -> Option<(RuntimeModule, String)> {
-        if let Some(idx) = name.rfind('.') {
-            let parts = name.split_at(idx);
-            let m = parts.0;
-            let f = &parts.1[1..];
-            self.find_module(m).map(|m| (m, f.to_string()))
-        } else {
-            None
+    pub fn set_module_loader(&mut self, loader: Option<Box<dyn ModuleLoader>>) {
+        *self.module_loader.get_mut() = loader;
+    }
+
+    // finds a module by name, asking the module loader for it if it is not
+    // loaded yet; a module that is only added does not replace anything, so
+    // the resolved call targets stay valid
+    pub fn find_or_load_module(&self, name: &str) -> Result<Option<RuntimeModule>, String> {
+        if let Some(m) = self.find_module(name) {
+            return Ok(Some(m));
+        }
+        let mut loader = self.module_loader.borrow_mut();
+        let Some(loader) = loader.as_mut() else {
+            return Ok(None);
+        };
+        let Some(mdef) = loader.load_module(name)? else {
+            return Ok(None);
+        };
+        log_info!(LOG_LOADER, "loaded module {}", name);
+        let m = RuntimeModule::from(&mdef);
+        self.modules.borrow_mut().insert(name.to_owned(), m.clone());
+        Ok(Some(m))
+    }
+
+    pub fn module_names(&self) -> Vec<String> {
+        let mut names = self
+            .modules
+            .borrow()
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        names.sort();
+        names
+    }
+
+    fn lookup_module_dotted(&self, name: &str) -> Result<(RuntimeModule, String), LookupError> {
+        let (m, f) = name.rsplit_once('.').ok_or(LookupError::Missing)?;
+        match self.find_or_load_module(m) {
+            Ok(Some(module)) => Ok((module, f.to_string())),
+            Ok(None) => Err(LookupError::Missing),
+            Err(err) => Err(LookupError::LoadFailed(m.to_owned(), err)),
         }
     }
 
     pub fn lookup_function(&self, name: &str) -> Option<RuntimeCallable> {
-        if let Some((m, f)) = self.lookup_module_dotted(name) {
-            m.find_function(&f)
+        self.try_lookup_function(name).ok()
+    }
+
+    // like lookup_function, but tells a missing function from a module that
+    // failed to load
+    pub fn try_lookup_function(&self, name: &str) -> Result<RuntimeCallable, LookupError> {
+        let (m, f) = self.lookup_module_dotted(name)?;
+        m.find_function(&f).ok_or(LookupError::Missing)
+    }
+
+    pub fn lookup_named_type(&self, name: &str) -> Option<RuntimeTypeDef> {
+        let (m, t) = self.lookup_module_dotted(name).ok()?;
+        m.find_named_type(&t)
+    }
+
+    pub fn resolve_function(
+        &self,
+        name: &str,
+        from: &RuntimeModule,
+    ) -> Result<RuntimeCallable, LookupError> {
+        let (m, f) = self.lookup_module_dotted(name)?;
+        let callable = m.find_function(&f).ok_or(LookupError::Missing)?;
+        if m.name() == from.name() || m.is_function_exported(&f) {
+            Ok(callable)
         } else {
-            None
+            Err(LookupError::NotExported)
         }
     }
 
-    pub fn lookup_named_type(&self, name: &str) -> Option<RuntimeTypeDef> {
-        if let Some((m, f)) = self.lookup_module_dotted(name) {
-            m.find_named_type(&f)
+    pub fn resolve_named_type(
+        &self,
+        name: &str,
+        from: &RuntimeModule,
+    ) -> Result<RuntimeTypeDef, LookupError> {
+        let (m, t) = self.lookup_module_dotted(name)?;
+        let tdef = m.find_named_type(&t).ok_or(LookupError::Missing)?;
+        if m.name() == from.name() || m.is_named_type_exported(&t) {
+            Ok(tdef)
         } else {
-            None
+            Err(LookupError::NotExported)
         }
     }
 
+    pub fn max_call_depth(&self) -> usize {
+        self.max_call_depth
+    }
+
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    pub fn instruction_budget(&self) -> Option<u64> {
+        self.instruction_budget
+    }
+
+    pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+        self.instruction_budget = budget;
+    }
+
+    pub fn refuel(&mut self, fuel: u64) {
+        let cur = self.instruction_budget.unwrap_or_default();
+        self.instruction_budget = Some(cur.saturating_add(fuel));
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended_base.is_some()
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn live_elements(&self) -> usize {
+        self.live_elements.live()
+    }
+
+    pub fn add_observer(&mut self, observer: Box<dyn ExecutionObserver>) -> ObserverId {
+        let id = self.next_observer;
+        self.next_observer = id.next();
+        self.observers.push((id, observer));
+        id
+    }
+
+    pub fn remove_observer(&mut self, id: ObserverId) -> Option<Box<dyn ExecutionObserver>> {
+        let idx = self.observers.iter().position(|(oid, _)| *oid == id)?;
+        if self.observers.len() == 1 {
+            self.skip_observers = false;
+        }
+        Some(self.observers.remove(idx).1)
+    }
+
+    pub fn has_observers(&self) -> bool {
+        !self.observers.is_empty()
+    }
+
+    // the command line arguments of the program being run
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    // values the embedding program hands to its natives, at most one per type;
+    // returns the value it replaces
+    pub fn set_host_data<T: Any>(&mut self, data: T) -> Option<T> {
+        self.host_data
+            .insert(TypeId::of::<T>(), Box::new(data))
+            .and_then(|old| old.downcast().ok())
+            .map(|old| *old)
+    }
+
+    pub fn host_data<T: Any>(&self) -> Option<&T> {
+        self.host_data
+            .get(&TypeId::of::<T>())
+            .and_then(|data| data.downcast_ref())
+    }
+
+    pub fn host_data_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.host_data
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|data| data.downcast_mut())
+    }
+
+    pub fn take_host_data<T: Any>(&mut self) -> Option<T> {
+        self.host_data
+            .remove(&TypeId::of::<T>())
+            .and_then(|data| data.downcast().ok())
+            .map(|data| *data)
+    }
+
+    pub fn args_value(&self) -> RuntimeValue {
+        let args = self
+            .args
+            .iter()
+            .map(|a| RuntimeValue::String(a.clone()))
+            .collect::<Vec<RuntimeValue>>();
+        RuntimeValue::Arr(Array::new_typed(RuntimeType::String, &args))
+    }
+
+    pub fn call_depth(&self) -> usize {
+        self.unwinder.len()
+    }
+
+    // runs a function to completion on a value stack of its own, holding just
+    // the arguments, and returns what it leaves there from bottom to top; the
+    // caller's stack and call frames are as they were afterwards, whatever the
+    // outcome, so a suspended run cannot be resumed
+    pub fn call(
+        &mut self,
+        name: &str,
+        args: &[RuntimeValue],
+    ) -> Result<Vec<RuntimeValue>, RunloopError> {
+        let f = self.try_lookup_function(name).map_err(|err| RunloopError {
+            cur_ptr: 0,
+            instruction: None,
+            data: match err {
+                LookupError::LoadFailed(m, err) => RunloopErrData::ModuleLoadFailed(m, err),
+                _ => RunloopErrData::MissingFunction(name.to_owned()),
+            },
+        })?;
+        if let Some(arity) = f.arity() {
+            if arity != args.len() {
+                return Err(RunloopError {
+                    cur_ptr: 0,
+                    instruction: None,
+                    data: RunloopErrData::ArityMismatch(arity, args.len()),
+                });
+            }
+        }
+
+        // a suspended run stays below the frames of this call, to be resumed
+        // once it returns
+        let base = self.unwinder.len();
+        let suspended_base = self.suspended_base.take();
+        let stack = Stack {
+            values: args.to_vec(),
+        };
+        let caller_stack = std::mem::replace(&mut self.runtime_stack, stack);
+        let result = run_loop(&f, self);
+        let results = std::mem::replace(&mut self.runtime_stack, caller_stack);
+        self.unwinder.split_off(base);
+        self.suspended_base = suspended_base;
+        result.map(|_| results.values)
+    }
+
+    // drops the frames a failed run left behind for print_unwind, so that the
+    // environment can run again from an empty call stack
+    pub fn clear_frames(&mut self) {
+        self.unwinder.split_off(0);
+        self.suspended_base = None;
+    }
+
     pub fn print_unwind(&self) -> String {
         format!("{}", self.unwinder)
     }
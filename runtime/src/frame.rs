@@ -1,9 +1,11 @@
-use crate::runtime_module::RuntimeCallable;
+use crate::{runtime_module::RuntimeCallable, values::RuntimeValue};
 
 #[derive(Clone)]
 pub struct Frame {
     function: RuntimeCallable,
     ip: Option<usize>,
+    pub(crate) resume_at: usize,
+    pub(crate) slots: Vec<RuntimeValue>,
 }
 
 impl Frame {
@@ -11,6 +13,8 @@ impl Frame {
         Self {
             function: f.clone(),
             ip: None,
+            resume_at: 0,
+            slots: vec![],
         }
     }
 
@@ -25,6 +29,10 @@ impl Frame {
     pub fn get_ip(&self) -> Option<usize> {
         self.ip
     }
+
+    pub fn get_slots(&self) -> &[RuntimeValue] {
+        &self.slots
+    }
 }
 
 impl std::fmt::Display for Frame {
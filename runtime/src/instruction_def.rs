@@ -39,6 +39,8 @@ pub enum InstructionDef {
     NEWREC,
     RECGET,
     RECSET,
+    CALLDIRECT(u16),
+    TAILCALL(u16),
 }
 // this file is autogenerated, do not edit manually
 // to change this file consult gen/genall.sh
@@ -82,6 +84,8 @@ impl InstructionDef {
             InstructionDef::NEWREC => 1,
             InstructionDef::RECGET => 1,
             InstructionDef::RECSET => 1,
+            InstructionDef::CALLDIRECT(_) => 1 + core::mem::size_of::<u16>(),
+            InstructionDef::TAILCALL(_) => 1 + core::mem::size_of::<u16>(),
         }
     }
 }
@@ -127,6 +131,8 @@ impl InstructionDef {
             InstructionDef::NEWREC => false,
             InstructionDef::RECGET => false,
             InstructionDef::RECSET => false,
+            InstructionDef::CALLDIRECT(_) => false,
+            InstructionDef::TAILCALL(_) => true,
         }
     }
 }
@@ -251,6 +257,14 @@ impl InstructionDef {
             InstructionDef::RECSET => {
                 bc.write_u8(u8::from(crate::opcodes::Opcode::RECSET));
             }
+            InstructionDef::CALLDIRECT(arg0) => {
+                bc.write_u8(u8::from(crate::opcodes::Opcode::CALLDIRECT));
+                bc.write_u16(*arg0);
+            }
+            InstructionDef::TAILCALL(arg0) => {
+                bc.write_u8(u8::from(crate::opcodes::Opcode::TAILCALL));
+                bc.write_u16(*arg0);
+            }
         }
     }
 }
@@ -39,6 +39,8 @@ pub enum RuntimeInstruction {
     NEWREC,
     RECGET,
     RECSET,
+    CALLDIRECT(u16),
+    TAILCALL(u16),
 }
 // this file is autogenerated, do not edit manually
 // to change this file consult gen/genall.sh
@@ -108,6 +110,16 @@ impl RuntimeInstruction {
             crate::opcodes::Opcode::NEWREC => Some((RuntimeInstruction::NEWREC, idx)),
             crate::opcodes::Opcode::RECGET => Some((RuntimeInstruction::RECGET, idx)),
             crate::opcodes::Opcode::RECSET => Some((RuntimeInstruction::RECSET, idx)),
+            crate::opcodes::Opcode::CALLDIRECT => {
+                let arg0 = bc.read_u16(idx);
+                idx += 2;
+                Some((RuntimeInstruction::CALLDIRECT(arg0), idx))
+            }
+            crate::opcodes::Opcode::TAILCALL => {
+                let arg0 = bc.read_u16(idx);
+                idx += 2;
+                Some((RuntimeInstruction::TAILCALL(arg0), idx))
+            }
             _ => None,
         }
     }
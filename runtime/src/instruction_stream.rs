@@ -0,0 +1,111 @@
+use crate::{bytecode::Bytecode, instruction_runtime::RuntimeInstruction};
+
+// a function body decoded once into RuntimeInstructions; jump targets are
+// rewritten from byte offsets to indices into the instruction list, and
+// the byte offset of each instruction is kept around for error reporting
+#[derive(Debug, Default)]
+pub struct InstructionStream {
+    instructions: Vec<RuntimeInstruction>,
+    offsets: Vec<usize>,
+    end: usize,
+    invalid: bool,
+}
+
+impl InstructionStream {
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&RuntimeInstruction> {
+        self.instructions.get(idx)
+    }
+
+    // the instruction at idx as the bytecode spells it, with jump targets as
+    // byte offsets rather than instruction indices
+    pub fn original(&self, idx: usize) -> Option<RuntimeInstruction> {
+        let to_offset = |dst: &u16| self.offset(*dst as usize) as u16;
+        self.instructions.get(idx).map(|inst| match inst {
+            RuntimeInstruction::JUMP(dst) => RuntimeInstruction::JUMP(to_offset(dst)),
+            RuntimeInstruction::JTRUE(dst) => RuntimeInstruction::JTRUE(to_offset(dst)),
+            _ => inst.clone(),
+        })
+    }
+
+    pub fn offset(&self, idx: usize) -> usize {
+        self.offsets.get(idx).copied().unwrap_or(self.end)
+    }
+
+    pub fn index_of_offset(&self, offset: usize) -> Option<usize> {
+        self.offsets.binary_search(&offset).ok()
+    }
+
+    pub fn is_invalid(&self) -> bool {
+        self.invalid
+    }
+
+    // None when dst is not the offset of an instruction, or its index does
+    // not fit a jump operand
+    fn rewrite_target(&self, dst: u16) -> Option<u16> {
+        self.index_of_offset(dst as usize)
+            .and_then(|idx| u16::try_from(idx).ok())
+    }
+
+    // drops the instructions from idx on, as if decoding had failed there
+    fn cut(&mut self, idx: usize) {
+        self.end = self.offsets[idx];
+        self.instructions.truncate(idx);
+        self.offsets.truncate(idx);
+        self.invalid = true;
+    }
+}
+
+impl From<&Bytecode> for InstructionStream {
+    fn from(bc: &Bytecode) -> Self {
+        let mut this = Self::default();
+        let mut ip = 0;
+        while ip < bc.len() {
+            match RuntimeInstruction::from_bytecode(bc, ip) {
+                Some((inst, next)) => {
+                    this.instructions.push(inst);
+                    this.offsets.push(ip);
+                    ip = next;
+                }
+                None => {
+                    this.invalid = true;
+                    break;
+                }
+            }
+        }
+        this.end = ip;
+
+        // a jump into the middle of an instruction or past the end makes the
+        // stream invalid from that jump on; jumps before it that land beyond
+        // the cut fail the same way when taken
+        let mut i = 0;
+        while i < this.instructions.len() {
+            let rewritten = match this.instructions[i] {
+                RuntimeInstruction::JUMP(dst) => {
+                    this.rewrite_target(dst).map(RuntimeInstruction::JUMP)
+                }
+                RuntimeInstruction::JTRUE(dst) => {
+                    this.rewrite_target(dst).map(RuntimeInstruction::JTRUE)
+                }
+                _ => Some(this.instructions[i].clone()),
+            };
+            match rewritten {
+                Some(inst) => this.instructions[i] = inst,
+                None => {
+                    this.cut(i);
+                    break;
+                }
+            }
+            i += 1;
+        }
+
+        this
+    }
+}
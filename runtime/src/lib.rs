@@ -1,12 +1,22 @@
+// lets code derived with runtime_derive name this crate from inside it
+extern crate self as runtime;
+
 pub mod builder;
+pub mod bundle;
 pub mod bytecode;
+pub mod coverage;
 pub mod environ;
 pub mod frame;
 pub mod instruction_def;
 pub mod instruction_runtime;
+pub mod instruction_stream;
 pub mod intern_value;
+pub mod limits;
 pub mod log;
 pub mod module_definition;
+pub mod module_loader;
+pub mod native_fn;
+pub mod observer;
 pub mod opcodes;
 pub mod runloop;
 pub mod runtime_module;
@@ -0,0 +1,36 @@
+use std::{cell::Cell, rc::Rc};
+
+// caps enforced by the runloop on what bytecode does; None means unlimited.
+// values that natives or host conversions create are not checked against them
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    // elements of the arrays and records that NEWARR and NEWREC created and
+    // that are still alive; arrays built by natives are not counted
+    pub max_live_elements: Option<usize>,
+    // length of each string constant PUSH loads from a module; bytecode has
+    // no way to grow a string, but natives may return longer ones
+    pub max_string_constant_bytes: Option<usize>,
+    // slots TOSLOT may create in one frame
+    pub max_slots: Option<usize>,
+    // values on the stack after each instruction and native call
+    pub max_stack_depth: Option<usize>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ElementCounter {
+    live: Rc<Cell<usize>>,
+}
+
+impl ElementCounter {
+    pub fn live(&self) -> usize {
+        self.live.get()
+    }
+
+    pub(crate) fn add(&self, n: usize) {
+        self.live.set(self.live.get() + n);
+    }
+
+    pub(crate) fn sub(&self, n: usize) {
+        self.live.set(self.live.get().saturating_sub(n));
+    }
+}
@@ -1,4 +1,10 @@
 use core::fmt;
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex, MutexGuard},
+};
+
+pub const LOG_ENV_VAR: &str = "TUKUN_LOG";
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum LogLevel {
@@ -33,6 +39,23 @@ impl Ord for LogLevel {
     }
 }
 
+impl std::str::FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warning" | "warn" => Ok(LogLevel::Warning),
+            "error" => Ok(LogLevel::Error),
+            "fatal" => Ok(LogLevel::Fatal),
+            _ => Err(format!("unknown log level {s}")),
+        }
+    }
+}
+
+// the level given here is the default; it can be overridden at runtime by
+// subsystem name, see configure
 #[derive(Clone, Copy)]
 pub struct LogSubsystem {
     pub name: &'static str,
@@ -49,20 +72,105 @@ macro_rules! log_subsystem {
     };
 }
 
-#[allow(clippy::missing_safety_doc)]
-pub unsafe fn _print<T: fmt::Write>(writer: *mut T, args: ::core::fmt::Arguments) {
-    writer
-        .as_mut()
-        .expect("invalid writer")
-        .write_fmt(args)
-        .expect("log write failed");
+pub type LogWriter = Box<dyn fmt::Write + Send>;
+
+struct LogState {
+    levels: HashMap<String, LogLevel>,
+    default_level: Option<LogLevel>,
+    writer: LogWriter,
+}
+
+static LOG_STATE: LazyLock<Mutex<LogState>> = LazyLock::new(|| {
+    let mut state = LogState {
+        levels: HashMap::new(),
+        default_level: None,
+        writer: Box::new(StderrWriter {}),
+    };
+    if let Ok(spec) = std::env::var(LOG_ENV_VAR) {
+        if let Err(err) = state.apply(&spec) {
+            eprintln!("ignoring {LOG_ENV_VAR}: {err}");
+        }
+    }
+    Mutex::new(state)
+});
+
+fn log_state() -> MutexGuard<'static, LogState> {
+    LOG_STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl LogState {
+    // a spec is a comma separated list of subsystem=level entries; a bare
+    // level applies to every subsystem without an entry of its own
+    fn apply(&mut self, spec: &str) -> Result<(), String> {
+        let mut levels = vec![];
+        let mut default_level = None;
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            match entry.split_once('=') {
+                Some((name, level)) => levels.push((name.trim().to_owned(), level.trim().parse()?)),
+                None => default_level = Some(entry.parse()?),
+            }
+        }
+        self.levels.extend(levels);
+        if default_level.is_some() {
+            self.default_level = default_level;
+        }
+        Ok(())
+    }
+
+    fn level(&self, subsystem: &LogSubsystem) -> LogLevel {
+        self.levels
+            .get(subsystem.name)
+            .cloned()
+            .or(self.default_level)
+            .unwrap_or(subsystem.level)
+    }
+}
+
+pub fn configure(spec: &str) -> Result<(), String> {
+    log_state().apply(spec)
+}
+
+// returns the level the subsystem had before, if any
+pub fn set_level(name: &str, level: Option<LogLevel>) -> Option<LogLevel> {
+    let mut state = log_state();
+    match level {
+        Some(level) => state.levels.insert(name.to_owned(), level),
+        None => state.levels.remove(name),
+    }
+}
+
+pub fn level(subsystem: &LogSubsystem) -> LogLevel {
+    log_state().level(subsystem)
+}
+
+pub fn is_enabled(subsystem: &LogSubsystem, level: LogLevel) -> bool {
+    level >= self::level(subsystem)
+}
+
+// returns the writer that was installed before
+pub fn set_writer(writer: LogWriter) -> LogWriter {
+    std::mem::replace(&mut log_state().writer, writer)
+}
+
+pub fn write_record(
+    file: &str,
+    line: u32,
+    subsystem: &LogSubsystem,
+    tag: &str,
+    args: fmt::Arguments,
+) {
+    let mut state = log_state();
+    let _ = state.writer.write_fmt(format_args!(
+        "{}:{} {} {}: {}\n",
+        file, line, subsystem.name, tag, args
+    ));
 }
 
 #[macro_export]
-macro_rules! log_do_write {
-    ($($arg:tt)*) => {
-        unsafe {
-            $crate::log::_print( std::ptr::addr_of_mut!(LOG_WRITER), format_args!($($arg)*));
+macro_rules! log_at {
+    ($subsystem:expr, $level:expr, $tag:literal, $($args:expr),*) => {
+        if $crate::log::is_enabled(&$subsystem, $level) {
+            $crate::log::write_record(file!(), line!(), &$subsystem, $tag, format_args!($($args),*));
         }
     };
 }
@@ -70,60 +178,35 @@ macro_rules! log_do_write {
 #[macro_export]
 macro_rules! log_debug {
     ($subsystem:expr, $($args:expr),*) => {
-        if $crate::log::LogLevel::Debug >= $subsystem.level {
-            let f = file!();
-            let l = line!();
-            $crate::log_do_write!("{}:{} {} {}: ", f, l, $subsystem.name, "D");
-            $crate::log_do_write!("{}\n", format_args!($($args),*));
-        }
+        $crate::log_at!($subsystem, $crate::log::LogLevel::Debug, "D", $($args),*)
     };
 }
 
 #[macro_export]
 macro_rules! log_info {
     ($subsystem:expr, $($args:expr),*) => {
-        if $crate::log::LogLevel::Info >= $subsystem.level {
-            let f = file!();
-            let l = line!();
-            $crate::log_do_write!("{}:{} {} {}: ", f, l, $subsystem.name, "I");
-            $crate::log_do_write!("{}\n", format_args!($($args),*));
-        }
+        $crate::log_at!($subsystem, $crate::log::LogLevel::Info, "I", $($args),*)
     };
 }
 
 #[macro_export]
 macro_rules! log_warning {
     ($subsystem:expr, $($args:expr),*) => {
-        if $crate::log::LogLevel::Warning >= $subsystem.level {
-            let f = file!();
-            let l = line!();
-            $crate::log_do_write!("{}:{} {} {}: ", f, l, $subsystem.name, "W");
-            $crate::log_do_write!("{}\n", format_args!($($args),*));
-        }
+        $crate::log_at!($subsystem, $crate::log::LogLevel::Warning, "W", $($args),*)
     };
 }
 
 #[macro_export]
 macro_rules! log_error {
     ($subsystem:expr, $($args:expr),*) => {
-        if $crate::log::LogLevel::Error >= $subsystem.level {
-            let f = file!();
-            let l = line!();
-            $crate::log_do_write!("{}:{} {} {}: ", f, l, $subsystem.name, "E");
-            $crate::log_do_write!("{}\n", format_args!($($args),*));
-        }
+        $crate::log_at!($subsystem, $crate::log::LogLevel::Error, "E", $($args),*)
     };
 }
 
 #[macro_export]
 macro_rules! log_fatal {
     ($subsystem:expr, $($args:expr),*) => {
-        if $crate::log::LogLevel::Fatal >= $subsystem.level {
-            let f = file!();
-            let l = line!();
-            $crate::log_do_write!("{}:{} {} {}: ", f, l, $subsystem.name, "F");
-            $crate::log_do_write!("{}\n", format_args!($($args),*));
-        }
+        $crate::log_at!($subsystem, $crate::log::LogLevel::Fatal, "F", $($args),*)
     };
 }
 
@@ -135,3 +218,46 @@ impl fmt::Write for StderrWriter {
         Ok(())
     }
 }
+
+pub struct FileWriter {
+    file: std::fs::File,
+}
+
+impl FileWriter {
+    pub fn create(path: &str) -> std::io::Result<Self> {
+        Ok(Self {
+            file: std::fs::File::create(path)?,
+        })
+    }
+}
+
+impl fmt::Write for FileWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        std::io::Write::write_all(&mut self.file, s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+// keeps everything written in memory; clones share the same buffer
+#[derive(Default, Clone)]
+pub struct BufferWriter {
+    buffer: Arc<Mutex<String>>,
+}
+
+impl BufferWriter {
+    pub fn contents(&self) -> String {
+        self.buffer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl fmt::Write for BufferWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.buffer
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_str(s);
+        Ok(())
+    }
+}
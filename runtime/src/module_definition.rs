@@ -2,10 +2,31 @@ use serde::{Deserialize, Serialize};
 
 use crate::{bytecode::Bytecode, intern_value::InternValue, types::typedef::TypeDef};
 
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Visibility {
+    Public,
+    // as in the assembler, where only items marked pub are exported. items
+    // built through FunctionDef::new or TypeDef::new used to default to
+    // Public; hosts that call them from other modules must now mark them
+    // with set_visibility(Visibility::Public)
+    #[default]
+    Private,
+}
+
+impl Visibility {
+    pub fn is_public(&self) -> bool {
+        matches!(self, Visibility::Public)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDef {
     name: String,
     body: Bytecode,
+    visibility: Visibility,
+    arity: Option<usize>,
+    labels: Vec<(String, usize)>,
+    lines: Vec<(usize, u32)>,
 }
 
 impl FunctionDef {
@@ -13,6 +34,10 @@ impl FunctionDef {
         Self {
             name: String::from(name),
             body,
+            visibility: Visibility::default(),
+            arity: None,
+            labels: vec![],
+            lines: vec![],
         }
     }
 
@@ -23,14 +48,104 @@ impl FunctionDef {
     pub fn body(&self) -> &Bytecode {
         &self.body
     }
+
+    pub fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    pub fn set_visibility(&mut self, v: Visibility) -> &mut Self {
+        self.visibility = v;
+        self
+    }
+
+    // the number of arguments the function takes, when it declares one
+    pub fn arity(&self) -> Option<usize> {
+        self.arity
+    }
+
+    pub fn set_arity(&mut self, arity: Option<usize>) -> &mut Self {
+        self.arity = arity;
+        self
+    }
+
+    pub fn add_label(&mut self, name: &str, offset: usize) -> &mut Self {
+        self.labels.push((name.to_owned(), offset));
+        self
+    }
+
+    pub fn labels(&self) -> std::slice::Iter<'_, (String, usize)> {
+        self.labels.iter()
+    }
+
+    pub fn find_label(&self, name: &str) -> Option<usize> {
+        self.labels
+            .iter()
+            .find(|(label, _)| label == name)
+            .map(|(_, offset)| *offset)
+    }
+
+    pub fn add_line(&mut self, offset: usize, line: u32) -> &mut Self {
+        self.lines.push((offset, line));
+        self
+    }
+
+    // source line of each instruction offset, for functions built from source
+    pub fn lines(&self) -> std::slice::Iter<'_, (usize, u32)> {
+        self.lines.iter()
+    }
+
+    pub fn find_line(&self, offset: usize) -> Option<u32> {
+        self.lines
+            .iter()
+            .find(|(o, _)| *o == offset)
+            .map(|(_, line)| *line)
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// a serialized module starts with these bytes and MODULE_FORMAT_VERSION as a
+// little endian u32, followed by the bincode of the ModuleDef
+pub const MODULE_MAGIC: &[u8; 8] = b"TUKUNMOD";
+
+// bincode has no field names, so any change to the serialized layout of
+// ModuleDef or the types in it must bump this
+pub const MODULE_FORMAT_VERSION: u32 = 1;
+
+pub(crate) fn write_header(magic: &[u8; 8]) -> Vec<u8> {
+    let mut bytes = magic.to_vec();
+    bytes.extend_from_slice(&MODULE_FORMAT_VERSION.to_le_bytes());
+    bytes
+}
+
+// returns what follows a header written by write_header
+pub(crate) fn check_header<'a>(
+    bytes: &'a [u8],
+    magic: &[u8; 8],
+    what: &str,
+) -> Result<&'a [u8], String> {
+    let rest = bytes
+        .strip_prefix(magic.as_slice())
+        .ok_or(format!("missing {what} header"))?;
+    if rest.len() < 4 {
+        return Err(format!("truncated {what} header"));
+    }
+    let (version, rest) = rest.split_at(4);
+    let version = u32::from_le_bytes(version.try_into().unwrap());
+    if version != MODULE_FORMAT_VERSION {
+        return Err(format!(
+            "{what} format version {version} is not supported, expected {MODULE_FORMAT_VERSION}"
+        ));
+    }
+    Ok(rest)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModuleDef {
     name: String,
     functions: Vec<FunctionDef>,
     named_types: Vec<TypeDef>,
     intern_values: Vec<InternValue>,
+    call_targets: Vec<String>,
+    source: Option<String>,
 }
 
 impl ModuleDef {
@@ -40,6 +155,8 @@ impl ModuleDef {
             functions: vec![],
             named_types: vec![],
             intern_values: vec![],
+            call_targets: vec![],
+            source: None,
         }
     }
 
@@ -56,6 +173,15 @@ impl ModuleDef {
         self.intern_values.len() - 1
     }
 
+    pub fn add_call_target(&mut self, name: &str) -> usize {
+        if let Some(idx) = self.call_targets.iter().position(|t| t == name) {
+            idx
+        } else {
+            self.call_targets.push(name.to_owned());
+            self.call_targets.len() - 1
+        }
+    }
+
     pub fn functions(&self) -> std::slice::Iter<'_, FunctionDef> {
         self.functions.iter()
     }
@@ -68,7 +194,34 @@ impl ModuleDef {
         self.intern_values.iter()
     }
 
+    pub fn call_targets(&self) -> std::slice::Iter<'_, String> {
+        self.call_targets.iter()
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub fn set_source(&mut self, source: &str) {
+        self.source = Some(source.to_owned());
+    }
+
+    pub fn is_module(bytes: &[u8]) -> bool {
+        bytes.starts_with(MODULE_MAGIC)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let mut bytes = write_header(MODULE_MAGIC);
+        bincode::serialize_into(&mut bytes, self).map_err(|err| format!("{err}"))?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let body = check_header(bytes, MODULE_MAGIC, "module")?;
+        bincode::deserialize(body).map_err(|err| format!("{err}"))
+    }
 }
@@ -0,0 +1,9 @@
+use crate::{log::LogSubsystem, log_subsystem, module_definition::ModuleDef};
+
+pub const LOG_LOADER: LogSubsystem = log_subsystem!("loader", crate::log::LogLevel::Error);
+
+// supplies modules that are not loaded yet when a lookup names them; Ok(None)
+// means the loader does not know the module
+pub trait ModuleLoader {
+    fn load_module(&mut self, name: &str) -> Result<Option<ModuleDef>, String>;
+}
@@ -0,0 +1,254 @@
+use std::marker::PhantomData;
+
+use crate::{
+    environ::Environment,
+    runloop::{InvalidTypeError, RunloopErrData, RunloopError, RunloopResult},
+    runtime_module::NativeCallable,
+    types::RuntimeType,
+    values::{
+        convert::{ConversionError, RuntimeTyped},
+        RuntimeValue,
+    },
+};
+
+// the type of one native parameter or result; the length of an array is part
+// of its runtime type, so natives that take arrays of any length use Array
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureType {
+    Any,
+    Array,
+    Of(RuntimeType),
+}
+
+impl SignatureType {
+    pub fn matches(&self, value: &RuntimeValue) -> bool {
+        match self {
+            SignatureType::Any => true,
+            SignatureType::Array => matches!(value, RuntimeValue::Arr(_)),
+            SignatureType::Of(t) => value.get_type() == *t,
+        }
+    }
+}
+
+impl std::fmt::Display for SignatureType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureType::Any => write!(f, "any"),
+            SignatureType::Array => write!(f, "array"),
+            SignatureType::Of(t) => write!(f, "{t}"),
+        }
+    }
+}
+
+// params are listed in push order, so the last one is on top of the stack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NativeSignature {
+    pub params: Vec<SignatureType>,
+    pub results: Vec<SignatureType>,
+}
+
+impl NativeSignature {
+    pub fn new(params: &[SignatureType], results: &[SignatureType]) -> Self {
+        Self {
+            params: params.to_vec(),
+            results: results.to_vec(),
+        }
+    }
+
+    // checks the operands of a call without popping them
+    pub fn check_params(&self, env: &Environment) -> RunloopResult {
+        let values = env.stack_values();
+        if values.len() < self.params.len() {
+            return Err(RunloopError {
+                cur_ptr: 0,
+                instruction: None,
+                data: RunloopErrData::EmptyStack,
+            });
+        }
+        let args = &values[values.len() - self.params.len()..];
+        for (param, arg) in self.params.iter().zip(args) {
+            if !param.matches(arg) {
+                return Err(RunloopError {
+                    cur_ptr: 0,
+                    instruction: None,
+                    data: RunloopErrData::InvalidType(InvalidTypeError {
+                        actual: arg.get_type(),
+                        expected: param.to_string(),
+                    }),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for NativeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |types: &[SignatureType]| {
+            types
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        write!(f, "({}) -> ({})", join(&self.params), join(&self.results))
+    }
+}
+
+// Rust types that closures passed to RuntimeModule::add_native_fn may take or
+// return, described for the signature of the native
+pub trait SignatureTyped {
+    fn signature_type() -> SignatureType;
+}
+
+impl<T: RuntimeTyped> SignatureTyped for T {
+    fn signature_type() -> SignatureType {
+        SignatureType::Of(T::runtime_type())
+    }
+}
+
+impl<T> SignatureTyped for Vec<T> {
+    fn signature_type() -> SignatureType {
+        SignatureType::Array
+    }
+}
+
+impl SignatureTyped for RuntimeValue {
+    fn signature_type() -> SignatureType {
+        SignatureType::Any
+    }
+}
+
+// what a closure registered with RuntimeModule::add_native_fn may return; ()
+// leaves the stack alone, anything convertible is pushed as one value, and an
+// Err fails the call
+pub trait NativeResult {
+    fn result_types() -> Vec<SignatureType>;
+    fn push_result(self, env: &mut Environment) -> RunloopResult;
+}
+
+impl NativeResult for () {
+    fn result_types() -> Vec<SignatureType> {
+        vec![]
+    }
+
+    fn push_result(self, _: &mut Environment) -> RunloopResult {
+        Ok(())
+    }
+}
+
+impl<T: Into<RuntimeValue> + SignatureTyped> NativeResult for T {
+    fn result_types() -> Vec<SignatureType> {
+        vec![T::signature_type()]
+    }
+
+    fn push_result(self, env: &mut Environment) -> RunloopResult {
+        env.push_value(self.into());
+        Ok(())
+    }
+}
+
+impl<T: NativeResult> NativeResult for Result<T, RunloopError> {
+    fn result_types() -> Vec<SignatureType> {
+        T::result_types()
+    }
+
+    fn push_result(self, env: &mut Environment) -> RunloopResult {
+        self?.push_result(env)
+    }
+}
+
+impl<T: NativeResult> NativeResult for Result<T, ConversionError> {
+    fn result_types() -> Vec<SignatureType> {
+        T::result_types()
+    }
+
+    fn push_result(self, env: &mut Environment) -> RunloopResult {
+        self?.push_result(env)
+    }
+}
+
+// a Rust function taking its arguments as Args; the last argument is the one
+// on top of the stack
+pub trait NativeFn<Args> {
+    fn signature(&self) -> NativeSignature;
+    fn call_with(&self, env: &mut Environment) -> RunloopResult;
+}
+
+fn pop_arguments(env: &mut Environment, count: usize) -> Result<Vec<RuntimeValue>, RunloopError> {
+    if env.stack_len() < count {
+        return Err(RunloopError {
+            cur_ptr: 0,
+            instruction: None,
+            data: RunloopErrData::EmptyStack,
+        });
+    }
+    let mut args = (0..count).map(|_| env.pop_value()).collect::<Vec<_>>();
+    args.reverse();
+    Ok(args)
+}
+
+macro_rules! native_fn_arity {
+    ($len:literal $(, $t:ident)*) => {
+        impl<Func, Res, $($t),*> NativeFn<($($t,)*)> for Func
+        where
+            Func: Fn($($t),*) -> Res,
+            Res: NativeResult,
+            $($t: TryFrom<RuntimeValue> + SignatureTyped, ConversionError: From<$t::Error>,)*
+        {
+            fn signature(&self) -> NativeSignature {
+                NativeSignature {
+                    params: vec![$($t::signature_type()),*],
+                    results: Res::result_types(),
+                }
+            }
+
+            #[allow(unused_mut, unused_variables)]
+            fn call_with(&self, env: &mut Environment) -> RunloopResult {
+                let mut args = pop_arguments(env, $len)?.into_iter();
+                let result = self($($t::try_from(args.next().unwrap()).map_err(ConversionError::from)?),*);
+                result.push_result(env)
+            }
+        }
+    };
+}
+
+native_fn_arity!(0);
+native_fn_arity!(1, A);
+native_fn_arity!(2, A, B);
+native_fn_arity!(3, A, B, C);
+native_fn_arity!(4, A, B, C, D);
+native_fn_arity!(5, A, B, C, D, E);
+native_fn_arity!(6, A, B, C, D, E, F);
+
+pub(crate) struct ClosureCallable<Args, Func> {
+    name: String,
+    signature: NativeSignature,
+    f: Func,
+    args: PhantomData<fn(Args)>,
+}
+
+impl<Args, Func: NativeFn<Args>> ClosureCallable<Args, Func> {
+    pub(crate) fn new(name: &str, f: Func) -> Self {
+        Self {
+            name: name.to_owned(),
+            signature: f.signature(),
+            f,
+            args: PhantomData,
+        }
+    }
+}
+
+impl<Args, Func: NativeFn<Args>> NativeCallable for ClosureCallable<Args, Func> {
+    fn call(&self, env: &mut Environment) -> RunloopResult {
+        self.f.call_with(env)
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn signature(&self) -> Option<&NativeSignature> {
+        Some(&self.signature)
+    }
+}
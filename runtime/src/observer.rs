@@ -0,0 +1,59 @@
+use crate::{
+    instruction_runtime::RuntimeInstruction, log::LogSubsystem, log_debug, log_subsystem,
+    runloop::RunloopError, runtime_module::RuntimeCallable, unwinder::Unwinder,
+    values::RuntimeValue,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObserverAction {
+    Continue,
+    Pause,
+}
+
+pub struct InstructionEvent<'a> {
+    pub function: &'a RuntimeCallable,
+    pub offset: usize,
+    // as encoded in the bytecode, so jump targets are byte offsets
+    pub instruction: &'a RuntimeInstruction,
+    pub stack: &'a [RuntimeValue],
+    pub depth: usize,
+}
+
+// observers registered on an Environment are told about every instruction the
+// runloop is about to execute, every frame pushed and popped and every error.
+// returning Pause from before_instruction stops the run with
+// RunloopErrData::Paused before the instruction executes; when resumed, the
+// observers are not told about that instruction a second time
+pub trait ExecutionObserver {
+    fn before_instruction(&mut self, _event: &InstructionEvent) -> ObserverAction {
+        ObserverAction::Continue
+    }
+
+    fn function_entry(&mut self, _f: &RuntimeCallable, _depth: usize) {}
+
+    fn function_exit(&mut self, _f: &RuntimeCallable, _depth: usize) {}
+
+    fn on_error(&mut self, _err: &RunloopError, _unwinder: &Unwinder) {}
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObserverId(usize);
+
+impl ObserverId {
+    pub(crate) fn next(self) -> Self {
+        Self(self.0 + 1)
+    }
+}
+
+pub const LOG_RUNLOOP: LogSubsystem = log_subsystem!("runloop", crate::log::LogLevel::Error);
+
+// logs each opcode to the runloop subsystem at debug level; hosts install it
+// when that level is enabled
+pub struct LogObserver {}
+
+impl ExecutionObserver for LogObserver {
+    fn before_instruction(&mut self, event: &InstructionEvent) -> ObserverAction {
+        log_debug!(LOG_RUNLOOP, "running opcode {:?}", event.instruction);
+        ObserverAction::Continue
+    }
+}
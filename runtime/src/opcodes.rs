@@ -40,6 +40,8 @@ pub enum Opcode {
     NEWREC = 34,
     RECGET = 35,
     RECSET = 36,
+    CALLDIRECT = 37,
+    TAILCALL = 38,
     MAX,
 }
 impl From<u8> for Opcode {
@@ -1,26 +1,26 @@
 use crate::{
-    bytecode::Bytecode,
-    environ::Environment,
+    environ::{Environment, LookupError},
+    frame::Frame,
     instruction_runtime::RuntimeInstruction,
-    log::{LogSubsystem, StderrWriter},
-    log_debug, log_subsystem,
+    instruction_stream::InstructionStream,
+    intern_value::InternValue,
+    observer::{InstructionEvent, ObserverAction},
     runtime_module::{RuntimeCallable, RuntimeModule},
     types::{array::ArrayType, record::RecordType, RuntimeType},
     values::{
         array::Array,
         comparators::{compare_values, CompareResult},
+        convert::ConversionError,
         record::Record,
         RuntimeValue,
     },
 };
 
-static LOG_RUNLOOP: LogSubsystem = log_subsystem!("runloop", crate::log::LogLevel::Error);
-static mut LOG_WRITER: StderrWriter = StderrWriter {};
-
 macro_rules! err_ret {
     ($ptr:expr, $payload:expr) => {
         return Err(RunloopError {
             cur_ptr: $ptr,
+            instruction: None,
             data: $payload,
         });
     };
@@ -73,25 +73,30 @@ macro_rules! typed_pop2 {
 }
 
 struct BytecodeContext<'a> {
+    f: &'a RuntimeCallable,
     m: &'a RuntimeModule,
-    b: &'a Bytecode,
+    c: &'a InstructionStream,
 }
 
 impl<'a> BytecodeContext<'a> {
-    fn body(&self) -> &'a Bytecode {
-        self.b
+    fn code(&self) -> &'a InstructionStream {
+        self.c
     }
 
     fn module(&self) -> &'a RuntimeModule {
         self.m
     }
+
+    fn function(&self) -> &'a RuntimeCallable {
+        self.f
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 #[allow(dead_code)]
 pub struct InvalidTypeError {
-    actual: RuntimeType,
-    expected: String,
+    pub(crate) actual: RuntimeType,
+    pub(crate) expected: String,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -100,20 +105,38 @@ pub enum RunloopErrData {
     InstrutionOutOfBounds,
     InvalidBytecode,
     MissingInternValue(u16),
+    MissingCallTarget(u16),
     InvalidOperands(RuntimeInstruction, Vec<RuntimeValue>),
     MissingFunction(String),
     MissingType(String),
+    PrivateFunction(String),
+    PrivateType(String),
     InvalidSlot(usize),
     InvalidType(InvalidTypeError),
+    StackOverflow(usize),
+    BudgetExhausted,
+    Paused,
+    ElementLimitExceeded(usize),
+    StringLimitExceeded(usize),
+    SlotLimitExceeded(usize),
+    ValueStackLimitExceeded(usize),
+    ArityMismatch(usize, usize),
+    SuspendedInNative,
+    ModuleLoadFailed(String, String),
+    OutputFailed(String),
 }
 
 #[derive(Debug)]
 #[allow(dead_code)]
 pub struct RunloopError {
     pub cur_ptr: usize,
+    // the instruction that failed, for errors raised while executing one;
+    // jump targets are byte offsets like cur_ptr
+    pub instruction: Option<RuntimeInstruction>,
     pub data: RunloopErrData,
 }
 
+// the instruction follows from the offset, so it is not compared
 impl PartialEq for RunloopError {
     fn eq(&self, other: &Self) -> bool {
         self.cur_ptr == other.cur_ptr && self.data == other.data
@@ -122,351 +145,824 @@ impl PartialEq for RunloopError {
 
 impl Eq for RunloopError {}
 
+impl std::fmt::Display for InvalidTypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected {}, found {}", self.expected, self.actual)
+    }
+}
+
+impl RunloopErrData {
+    // errors caused by names that do not resolve, rather than by the values
+    // the program computed
+    pub fn is_link_error(&self) -> bool {
+        matches!(
+            self,
+            RunloopErrData::MissingCallTarget(_)
+                | RunloopErrData::MissingFunction(_)
+                | RunloopErrData::MissingType(_)
+                | RunloopErrData::PrivateFunction(_)
+                | RunloopErrData::PrivateType(_)
+        )
+    }
+}
+
+impl std::fmt::Display for RunloopErrData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RunloopErrData::EmptyStack => write!(f, "pop from an empty value stack"),
+            RunloopErrData::InstrutionOutOfBounds => {
+                write!(f, "instruction pointer past the end of the function")
+            }
+            RunloopErrData::InvalidBytecode => write!(f, "invalid bytecode"),
+            RunloopErrData::MissingInternValue(idx) => {
+                write!(f, "no interned value at index {idx}")
+            }
+            RunloopErrData::MissingCallTarget(idx) => write!(f, "no call target at index {idx}"),
+            RunloopErrData::InvalidOperands(inst, values) => {
+                let values = values
+                    .iter()
+                    .map(|v| format!("{v}"))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "invalid operands for {inst:?}: [{values}]")
+            }
+            RunloopErrData::MissingFunction(name) => write!(f, "function {name} not found"),
+            RunloopErrData::MissingType(name) => write!(f, "type {name} not found"),
+            RunloopErrData::PrivateFunction(name) => write!(f, "function {name} is not exported"),
+            RunloopErrData::PrivateType(name) => write!(f, "type {name} is not exported"),
+            RunloopErrData::InvalidSlot(slot) => write!(f, "slot {slot} is not set"),
+            RunloopErrData::InvalidType(err) => write!(f, "invalid type: {err}"),
+            RunloopErrData::StackOverflow(max) => {
+                write!(f, "call depth exceeded the maximum of {max}")
+            }
+            RunloopErrData::BudgetExhausted => write!(f, "instruction budget exhausted"),
+            RunloopErrData::Paused => write!(f, "paused by an observer"),
+            RunloopErrData::ElementLimitExceeded(max) => {
+                write!(f, "live elements exceeded the limit of {max}")
+            }
+            RunloopErrData::StringLimitExceeded(max) => {
+                write!(f, "string constant longer than the limit of {max} bytes")
+            }
+            RunloopErrData::SlotLimitExceeded(max) => {
+                write!(f, "slot index beyond the limit of {max} slots")
+            }
+            RunloopErrData::ValueStackLimitExceeded(max) => {
+                write!(f, "value stack exceeded the limit of {max} values")
+            }
+            RunloopErrData::ArityMismatch(expected, given) => {
+                write!(f, "expected {expected} arguments, given {given}")
+            }
+            RunloopErrData::SuspendedInNative => {
+                write!(f, "cannot suspend a run entered from a native function")
+            }
+            RunloopErrData::ModuleLoadFailed(name, err) => {
+                write!(f, "unable to load module {name}: {err}")
+            }
+            RunloopErrData::OutputFailed(err) => write!(f, "writing output failed: {err}"),
+        }
+    }
+}
+
+impl std::fmt::Display for RunloopError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.instruction {
+            // invalid operands already name the instruction
+            Some(inst) if !matches!(self.data, RunloopErrData::InvalidOperands(..)) => {
+                write!(f, "{} in {inst:?} (at offset {})", self.data, self.cur_ptr)
+            }
+            _ => write!(f, "{} (at offset {})", self.data, self.cur_ptr),
+        }
+    }
+}
+
+impl std::error::Error for RunloopError {}
+
+impl From<ConversionError> for RunloopErrData {
+    fn from(err: ConversionError) -> Self {
+        RunloopErrData::InvalidType(InvalidTypeError {
+            actual: err.found,
+            expected: err.expected,
+        })
+    }
+}
+
+// conversions happen outside of any bytecode, so there is no offset to report
+impl From<ConversionError> for RunloopError {
+    fn from(err: ConversionError) -> Self {
+        RunloopError {
+            cur_ptr: 0,
+            instruction: None,
+            data: err.into(),
+        }
+    }
+}
+
 pub type RunloopResult = Result<(), RunloopError>;
 
-fn bytecode_run_loop<'a>(ctx: &'a BytecodeContext<'a>, env: &mut Environment) -> RunloopResult {
-    let mut slots: Vec<RuntimeValue> = vec![];
+// the frames and value stack of a run that ran out of instruction budget or
+// was paused by an ExecutionObserver, detached from the Environment so that the
+// host can keep it aside, run other code and continue it later. the whole value
+// stack goes with the run, as it may have popped values pushed before it started
+pub struct SuspendedRun {
+    frames: Vec<Frame>,
+    stack: Vec<RuntimeValue>,
+}
 
-    let mut ip: usize = 0;
-    loop {
-        let cur_ptr = ip;
-        env.unwinder.set_ip(cur_ptr);
+impl SuspendedRun {
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn stack(&self) -> &[RuntimeValue] {
+        &self.stack
+    }
 
-        if cur_ptr >= ctx.body().len() {
-            err_ret!(cur_ptr, RunloopErrData::InstrutionOutOfBounds);
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+pub enum RunloopStatus {
+    Finished,
+    Suspended(SuspendedRun),
+}
+
+enum FrameExit {
+    Return,
+    Call(RuntimeCallable),
+    TailCall(RuntimeCallable),
+}
+
+type FrameResult = Result<FrameExit, RunloopError>;
+
+fn resolve_call_target(
+    ctx: &BytecodeContext,
+    env: &mut Environment,
+    idx: u16,
+    cur_ptr: usize,
+) -> Result<RuntimeCallable, RunloopError> {
+    if let Some(f) = ctx.module().resolved_target(idx, env.targets_generation) {
+        return Ok(f);
+    }
+    let n = ctx.module().get_call_target_name(idx);
+    if n.is_none() {
+        err_ret!(cur_ptr, RunloopErrData::MissingCallTarget(idx));
+    }
+    let n = n.unwrap();
+    match env.resolve_function(&n, ctx.module()) {
+        Ok(f) => {
+            ctx.module()
+                .set_resolved_target(idx, env.targets_generation, f.clone());
+            Ok(f)
         }
-        let opcode_maybe = RuntimeInstruction::from_bytecode(ctx.body(), cur_ptr);
-        if opcode_maybe.is_none() {
-            err_ret!(cur_ptr, RunloopErrData::InvalidBytecode);
+        Err(LookupError::Missing) => {
+            err_ret!(cur_ptr, RunloopErrData::MissingFunction(n));
         }
-        let (inst, new_ip) = opcode_maybe.unwrap();
-        ip = new_ip;
+        Err(LookupError::NotExported) => {
+            err_ret!(cur_ptr, RunloopErrData::PrivateFunction(n));
+        }
+        Err(LookupError::LoadFailed(m, err)) => {
+            err_ret!(cur_ptr, RunloopErrData::ModuleLoadFailed(m, err));
+        }
+    }
+}
 
-        log_debug!(LOG_RUNLOOP, "running opcode {inst:?}");
+fn bytecode_run_loop<'a>(
+    ctx: &'a BytecodeContext<'a>,
+    env: &mut Environment,
+    ip: &mut usize,
+    slots: &mut Vec<RuntimeValue>,
+) -> FrameResult {
+    let code = ctx.code();
+    loop {
+        let cur_idx = *ip;
+        let cur_ptr = code.offset(cur_idx);
 
-        match inst {
-            RuntimeInstruction::NOP => {}
-            RuntimeInstruction::POP => {
-                stack_pop!(cur_ptr, env, inst);
+        if let Some(budget) = env.instruction_budget {
+            if budget == 0 {
+                err_ret!(cur_ptr, RunloopErrData::BudgetExhausted);
             }
-            RuntimeInstruction::DUP => {
-                let val = stack_pop!(cur_ptr, env, inst);
-                env.runtime_stack.push(val.clone());
-                env.runtime_stack.push(val);
-            }
-            RuntimeInstruction::SWAP => {
-                let x = stack_pop!(cur_ptr, env, inst);
-                let y = stack_pop!(cur_ptr, env, inst);
-                env.runtime_stack.push(x);
-                env.runtime_stack.push(y);
-            }
-            RuntimeInstruction::PUSH(idx) => {
-                let iv = ctx.module().get_intern_value(idx);
-                if iv.is_none() {
-                    err_ret!(cur_ptr, RunloopErrData::MissingInternValue(idx));
-                }
-                let iv = iv.unwrap();
-                env.runtime_stack.push(RuntimeValue::from(iv.as_ref()));
-            }
-            RuntimeInstruction::ADD => {
-                let x = stack_pop!(cur_ptr, env, inst);
-                let y = stack_pop!(cur_ptr, env, inst);
-                match (&x, &y) {
-                    (RuntimeValue::Integer(x), RuntimeValue::Integer(y)) => env
-                        .runtime_stack
-                        .push(RuntimeValue::Integer(x.wrapping_add(*y))),
-                    (RuntimeValue::Float(x), RuntimeValue::Float(y)) => {
-                        env.runtime_stack.push(RuntimeValue::Float(x + y))
+            env.instruction_budget = Some(budget - 1);
+        }
+
+        if !env.observers.is_empty() && !std::mem::take(&mut env.skip_observers) {
+            if let Some(inst) = code.original(cur_idx) {
+                let event = InstructionEvent {
+                    function: ctx.function(),
+                    offset: cur_ptr,
+                    instruction: &inst,
+                    stack: &env.runtime_stack.values,
+                    depth: env.unwinder.len(),
+                };
+                let mut action = ObserverAction::Continue;
+                for (_, observer) in env.observers.iter_mut() {
+                    if observer.before_instruction(&event) == ObserverAction::Pause {
+                        action = ObserverAction::Pause;
                     }
-                    (_, _) => {
-                        err_ret!(cur_ptr, RunloopErrData::InvalidOperands(inst, vec![x, y]));
+                }
+                if action == ObserverAction::Pause {
+                    env.skip_observers = true;
+                    if let Some(budget) = env.instruction_budget {
+                        env.instruction_budget = Some(budget + 1);
                     }
+                    err_ret!(cur_ptr, RunloopErrData::Paused);
                 }
             }
-            RuntimeInstruction::SUB => {
-                let x = stack_pop!(cur_ptr, env, inst);
-                let y = stack_pop!(cur_ptr, env, inst);
-                match (&x, &y) {
-                    (RuntimeValue::Integer(x), RuntimeValue::Integer(y)) => {
-                        env.runtime_stack
-                            .push(RuntimeValue::Integer(x.wrapping_sub(*y)));
-                    }
-                    (RuntimeValue::Float(x), RuntimeValue::Float(y)) => {
-                        env.runtime_stack.push(RuntimeValue::Float(x - y));
-                    }
-                    (_, _) => {
-                        err_ret!(cur_ptr, RunloopErrData::InvalidOperands(inst, vec![x, y]));
-                    }
+        }
+
+        let inst = match code.get(cur_idx) {
+            Some(inst) => inst.clone(),
+            None => {
+                if cur_idx >= code.len() && code.is_invalid() {
+                    err_ret!(cur_ptr, RunloopErrData::InvalidBytecode);
                 }
+                err_ret!(cur_ptr, RunloopErrData::InstrutionOutOfBounds);
             }
-            RuntimeInstruction::EQ => {
-                let x = stack_pop!(cur_ptr, env, inst);
-                let y = stack_pop!(cur_ptr, env, inst);
-                let cmp = compare_values(&x, &y, false);
-                env.runtime_stack
-                    .push(RuntimeValue::Logical(cmp == CompareResult::EqualTo));
+        };
+        *ip += 1;
+
+        match execute_instruction(ctx, env, ip, slots, inst, cur_ptr) {
+            Ok(Some(exit)) => return Ok(exit),
+            Ok(None) => {}
+            Err(mut err) => {
+                err.instruction = code.original(cur_idx);
+                if let (RunloopErrData::InvalidOperands(inst, _), Some(original)) =
+                    (&mut err.data, &err.instruction)
+                {
+                    original.clone_into(inst);
+                }
+                return Err(err);
             }
-            RuntimeInstruction::GT => {
-                let x = stack_pop!(cur_ptr, env, inst);
-                let y = stack_pop!(cur_ptr, env, inst);
-                let cmp = compare_values(&x, &y, false);
-                env.runtime_stack
-                    .push(RuntimeValue::Logical(cmp == CompareResult::GreaterThan));
+        }
+    }
+}
+
+// runs one instruction; frame changes are handed back to the caller
+fn execute_instruction<'a>(
+    ctx: &'a BytecodeContext<'a>,
+    env: &mut Environment,
+    ip: &mut usize,
+    slots: &mut Vec<RuntimeValue>,
+    inst: RuntimeInstruction,
+    cur_ptr: usize,
+) -> Result<Option<FrameExit>, RunloopError> {
+    match inst {
+        RuntimeInstruction::NOP => {}
+        RuntimeInstruction::POP => {
+            stack_pop!(cur_ptr, env, inst);
+        }
+        RuntimeInstruction::DUP => {
+            let val = stack_pop!(cur_ptr, env, inst);
+            env.runtime_stack.push(val.clone());
+            env.runtime_stack.push(val);
+        }
+        RuntimeInstruction::SWAP => {
+            let x = stack_pop!(cur_ptr, env, inst);
+            let y = stack_pop!(cur_ptr, env, inst);
+            env.runtime_stack.push(x);
+            env.runtime_stack.push(y);
+        }
+        RuntimeInstruction::PUSH(idx) => {
+            let iv = ctx.module().get_intern_value(idx);
+            if iv.is_none() {
+                err_ret!(cur_ptr, RunloopErrData::MissingInternValue(idx));
+            }
+            let iv = iv.unwrap();
+            if let (InternValue::String(sv), Some(max)) =
+                (iv.as_ref(), env.limits.max_string_constant_bytes)
+            {
+                if sv.len() > max {
+                    err_ret!(cur_ptr, RunloopErrData::StringLimitExceeded(max));
+                }
             }
-            RuntimeInstruction::SGT => {
-                let x = stack_pop!(cur_ptr, env, inst);
-                let y = stack_pop!(cur_ptr, env, inst);
-                if x.is_integer() && y.is_integer() {
-                    let cmp = compare_values(&x, &y, true);
-                    env.runtime_stack
-                        .push(RuntimeValue::Logical(cmp == CompareResult::GreaterThan));
-                } else {
+            env.runtime_stack.push(RuntimeValue::from(iv.as_ref()));
+        }
+        RuntimeInstruction::ADD => {
+            let x = stack_pop!(cur_ptr, env, inst);
+            let y = stack_pop!(cur_ptr, env, inst);
+            match (&x, &y) {
+                (RuntimeValue::Integer(x), RuntimeValue::Integer(y)) => env
+                    .runtime_stack
+                    .push(RuntimeValue::Integer(x.wrapping_add(*y))),
+                (RuntimeValue::Float(x), RuntimeValue::Float(y)) => {
+                    env.runtime_stack.push(RuntimeValue::Float(x + y))
+                }
+                (_, _) => {
                     err_ret!(cur_ptr, RunloopErrData::InvalidOperands(inst, vec![x, y]));
                 }
             }
-            RuntimeInstruction::LT => {
-                let x = stack_pop!(cur_ptr, env, inst);
-                let y = stack_pop!(cur_ptr, env, inst);
-                let cmp = compare_values(&x, &y, false);
-                env.runtime_stack
-                    .push(RuntimeValue::Logical(cmp == CompareResult::LessThan));
-            }
-            RuntimeInstruction::SLT => {
-                let x = stack_pop!(cur_ptr, env, inst);
-                let y = stack_pop!(cur_ptr, env, inst);
-                if x.is_integer() && y.is_integer() {
-                    let cmp = compare_values(&x, &y, true);
+        }
+        RuntimeInstruction::SUB => {
+            let x = stack_pop!(cur_ptr, env, inst);
+            let y = stack_pop!(cur_ptr, env, inst);
+            match (&x, &y) {
+                (RuntimeValue::Integer(x), RuntimeValue::Integer(y)) => {
                     env.runtime_stack
-                        .push(RuntimeValue::Logical(cmp == CompareResult::LessThan));
-                } else {
-                    err_ret!(cur_ptr, RunloopErrData::InvalidOperands(inst, vec![x, y]));
+                        .push(RuntimeValue::Integer(x.wrapping_sub(*y)));
                 }
-            }
-            RuntimeInstruction::JUMP(dst) => {
-                ip = dst as usize;
-            }
-            RuntimeInstruction::JTRUE(dst) => {
-                let b = typed_pop!(cur_ptr, env, inst, RuntimeValue::Logical);
-                if b {
-                    ip = dst as usize;
+                (RuntimeValue::Float(x), RuntimeValue::Float(y)) => {
+                    env.runtime_stack.push(RuntimeValue::Float(x - y));
+                }
+                (_, _) => {
+                    err_ret!(cur_ptr, RunloopErrData::InvalidOperands(inst, vec![x, y]));
                 }
             }
-            RuntimeInstruction::NOT => {
-                let b = typed_pop!(cur_ptr, env, inst, RuntimeValue::Logical);
-                env.runtime_stack.push(crate::rv_bool!(!b));
+        }
+        RuntimeInstruction::EQ => {
+            let x = stack_pop!(cur_ptr, env, inst);
+            let y = stack_pop!(cur_ptr, env, inst);
+            let cmp = compare_values(&x, &y, false);
+            env.runtime_stack
+                .push(RuntimeValue::Logical(cmp == CompareResult::EqualTo));
+        }
+        RuntimeInstruction::GT => {
+            let x = stack_pop!(cur_ptr, env, inst);
+            let y = stack_pop!(cur_ptr, env, inst);
+            let cmp = compare_values(&x, &y, false);
+            env.runtime_stack
+                .push(RuntimeValue::Logical(cmp == CompareResult::GreaterThan));
+        }
+        RuntimeInstruction::SGT => {
+            let x = stack_pop!(cur_ptr, env, inst);
+            let y = stack_pop!(cur_ptr, env, inst);
+            if x.is_integer() && y.is_integer() {
+                let cmp = compare_values(&x, &y, true);
+                env.runtime_stack
+                    .push(RuntimeValue::Logical(cmp == CompareResult::GreaterThan));
+            } else {
+                err_ret!(cur_ptr, RunloopErrData::InvalidOperands(inst, vec![x, y]));
             }
-            RuntimeInstruction::AND => {
-                let (b1, b2) = typed_pop2!(
-                    cur_ptr,
-                    env,
-                    inst,
-                    RuntimeValue::Logical,
-                    RuntimeValue::Logical
-                );
-                env.runtime_stack.push(crate::rv_bool!(b1 && b2));
+        }
+        RuntimeInstruction::LT => {
+            let x = stack_pop!(cur_ptr, env, inst);
+            let y = stack_pop!(cur_ptr, env, inst);
+            let cmp = compare_values(&x, &y, false);
+            env.runtime_stack
+                .push(RuntimeValue::Logical(cmp == CompareResult::LessThan));
+        }
+        RuntimeInstruction::SLT => {
+            let x = stack_pop!(cur_ptr, env, inst);
+            let y = stack_pop!(cur_ptr, env, inst);
+            if x.is_integer() && y.is_integer() {
+                let cmp = compare_values(&x, &y, true);
+                env.runtime_stack
+                    .push(RuntimeValue::Logical(cmp == CompareResult::LessThan));
+            } else {
+                err_ret!(cur_ptr, RunloopErrData::InvalidOperands(inst, vec![x, y]));
             }
-            RuntimeInstruction::OR => {
-                let (b1, b2) = typed_pop2!(
-                    cur_ptr,
-                    env,
-                    inst,
-                    RuntimeValue::Logical,
-                    RuntimeValue::Logical
-                );
-                env.runtime_stack.push(crate::rv_bool!(b1 || b2));
+        }
+        RuntimeInstruction::JUMP(dst) => {
+            *ip = dst as usize;
+        }
+        RuntimeInstruction::JTRUE(dst) => {
+            let b = typed_pop!(cur_ptr, env, inst, RuntimeValue::Logical);
+            if b {
+                *ip = dst as usize;
             }
-            RuntimeInstruction::RET => return Ok(()),
-            RuntimeInstruction::FLOOKUP => {
-                let n = typed_pop!(cur_ptr, env, inst, RuntimeValue::String);
-                if let Some(f) = env.lookup_function(&n) {
-                    env.runtime_stack.push(RuntimeValue::Function(f));
-                } else {
+        }
+        RuntimeInstruction::NOT => {
+            let b = typed_pop!(cur_ptr, env, inst, RuntimeValue::Logical);
+            env.runtime_stack.push(crate::rv_bool!(!b));
+        }
+        RuntimeInstruction::AND => {
+            let (b1, b2) = typed_pop2!(
+                cur_ptr,
+                env,
+                inst,
+                RuntimeValue::Logical,
+                RuntimeValue::Logical
+            );
+            env.runtime_stack.push(crate::rv_bool!(b1 && b2));
+        }
+        RuntimeInstruction::OR => {
+            let (b1, b2) = typed_pop2!(
+                cur_ptr,
+                env,
+                inst,
+                RuntimeValue::Logical,
+                RuntimeValue::Logical
+            );
+            env.runtime_stack.push(crate::rv_bool!(b1 || b2));
+        }
+        RuntimeInstruction::RET => return Ok(Some(FrameExit::Return)),
+        RuntimeInstruction::FLOOKUP => {
+            let n = typed_pop!(cur_ptr, env, inst, RuntimeValue::String);
+            match env.resolve_function(&n, ctx.module()) {
+                Ok(f) => env.runtime_stack.push(RuntimeValue::Function(f)),
+                Err(LookupError::Missing) => {
                     err_ret!(cur_ptr, RunloopErrData::MissingFunction(n));
                 }
+                Err(LookupError::NotExported) => {
+                    err_ret!(cur_ptr, RunloopErrData::PrivateFunction(n));
+                }
+                Err(LookupError::LoadFailed(m, err)) => {
+                    err_ret!(cur_ptr, RunloopErrData::ModuleLoadFailed(m, err));
+                }
             }
-            RuntimeInstruction::TLOOKUP => {
-                let n = typed_pop!(cur_ptr, env, inst, RuntimeValue::String);
-                if let Some(t) = env.lookup_named_type(&n) {
-                    env.runtime_stack
-                        .push(RuntimeValue::Type(t.target().clone()));
-                } else {
+        }
+        RuntimeInstruction::TLOOKUP => {
+            let n = typed_pop!(cur_ptr, env, inst, RuntimeValue::String);
+            match env.resolve_named_type(&n, ctx.module()) {
+                Ok(t) => env
+                    .runtime_stack
+                    .push(RuntimeValue::Type(t.target().clone())),
+                Err(LookupError::Missing) => {
                     err_ret!(cur_ptr, RunloopErrData::MissingType(n));
                 }
+                Err(LookupError::NotExported) => {
+                    err_ret!(cur_ptr, RunloopErrData::PrivateType(n));
+                }
+                Err(LookupError::LoadFailed(m, err)) => {
+                    err_ret!(cur_ptr, RunloopErrData::ModuleLoadFailed(m, err));
+                }
             }
-            RuntimeInstruction::TYPEOF => {
-                let x = stack_pop!(cur_ptr, env, inst);
-                env.runtime_stack.push(RuntimeValue::Type(x.get_type()))
-            }
-            RuntimeInstruction::TOSLOT(slot) => {
-                let slot = slot as usize;
-                let x = stack_pop!(cur_ptr, env, inst);
-                match slot.cmp(&slots.len()) {
-                    std::cmp::Ordering::Less => {
-                        slots[slot] = x;
-                    }
-                    std::cmp::Ordering::Equal => {
-                        slots.push(x);
-                    }
-                    std::cmp::Ordering::Greater => {
-                        err_ret!(cur_ptr, RunloopErrData::InvalidSlot(slot));
+        }
+        RuntimeInstruction::TYPEOF => {
+            let x = stack_pop!(cur_ptr, env, inst);
+            env.runtime_stack.push(RuntimeValue::Type(x.get_type()))
+        }
+        RuntimeInstruction::TOSLOT(slot) => {
+            let slot = slot as usize;
+            let x = stack_pop!(cur_ptr, env, inst);
+            match slot.cmp(&slots.len()) {
+                std::cmp::Ordering::Less => {
+                    slots[slot] = x;
+                }
+                std::cmp::Ordering::Equal => {
+                    if let Some(max) = env.limits.max_slots {
+                        if slots.len() >= max {
+                            err_ret!(cur_ptr, RunloopErrData::SlotLimitExceeded(max));
+                        }
                     }
+                    slots.push(x);
+                }
+                std::cmp::Ordering::Greater => {
+                    err_ret!(cur_ptr, RunloopErrData::InvalidSlot(slot));
                 }
             }
-            RuntimeInstruction::FROMSLOT(slot) => {
-                let x = slots[slot as usize].clone();
-                env.runtime_stack.push(x);
-            }
-            RuntimeInstruction::CALL => {
-                let f = typed_pop!(cur_ptr, env, inst, RuntimeValue::Function);
-                let result = run_loop(&f, env);
-                result?;
-            }
-            RuntimeInstruction::NEWARR => {
-                let at = typed_pop!(cur_ptr, env, inst, RuntimeValue::Type);
-                if let RuntimeType::Arr(at) = at {
-                    let et = at.value_type;
-                    let len = at.len;
-                    let mut values = Vec::<RuntimeValue>::with_capacity(len);
-                    for _ in 0..len {
-                        let val = stack_pop!(cur_ptr, env, inst);
-                        assert!(val.get_type() == et);
-                        values.insert(0, val);
-                    }
-                    let arr = Array::new_inferred(&values);
-                    env.runtime_stack.push(RuntimeValue::Arr(arr));
-                } else {
-                    err_ret!(
-                        cur_ptr,
-                        RunloopErrData::InvalidType(InvalidTypeError {
-                            actual: at,
-                            expected: "array".to_owned()
-                        })
-                    );
+        }
+        RuntimeInstruction::FROMSLOT(slot) => {
+            let slot = slot as usize;
+            match slots.get(slot) {
+                Some(x) => env.runtime_stack.push(x.clone()),
+                None => {
+                    err_ret!(cur_ptr, RunloopErrData::InvalidSlot(slot));
                 }
             }
-            RuntimeInstruction::NEWREC => {
-                let rt = typed_pop!(cur_ptr, env, inst, RuntimeValue::Type);
-                if let RuntimeType::Record(rt) = rt {
-                    let len = rt.len();
-                    let mut values = Vec::<RuntimeValue>::with_capacity(len);
-                    for _ in 0..len {
-                        let val = stack_pop!(cur_ptr, env, inst);
-                        values.insert(0, val);
+        }
+        RuntimeInstruction::CALL => {
+            let f = typed_pop!(cur_ptr, env, inst, RuntimeValue::Function);
+            env.unwinder.set_ip(cur_ptr);
+            return Ok(Some(FrameExit::Call(f)));
+        }
+        RuntimeInstruction::CALLDIRECT(idx) => {
+            let f = resolve_call_target(ctx, env, idx, cur_ptr)?;
+            env.unwinder.set_ip(cur_ptr);
+            return Ok(Some(FrameExit::Call(f)));
+        }
+        RuntimeInstruction::TAILCALL(idx) => {
+            let f = resolve_call_target(ctx, env, idx, cur_ptr)?;
+            return Ok(Some(FrameExit::TailCall(f)));
+        }
+        RuntimeInstruction::NEWARR => {
+            let at = typed_pop!(cur_ptr, env, inst, RuntimeValue::Type);
+            if let RuntimeType::Arr(at) = at {
+                let et = at.value_type;
+                let len = at.len;
+                check_elements(env, len, cur_ptr)?;
+                let mut values =
+                    Vec::<RuntimeValue>::with_capacity(std::cmp::min(len, env.runtime_stack.len()));
+                for _ in 0..len {
+                    let val = stack_pop!(cur_ptr, env, inst);
+                    if val.get_type() != et {
+                        err_ret!(
+                            cur_ptr,
+                            RunloopErrData::InvalidType(InvalidTypeError {
+                                actual: val.get_type(),
+                                expected: String::from(&et),
+                            })
+                        );
                     }
-                    let rc = Record::new_typed(*rt, &values);
-                    env.runtime_stack.push(RuntimeValue::Record(rc));
-                } else {
-                    err_ret!(
-                        cur_ptr,
-                        RunloopErrData::InvalidType(InvalidTypeError {
-                            actual: rt,
-                            expected: "record".to_owned()
-                        })
-                    );
+                    values.push(val);
                 }
-            }
-            RuntimeInstruction::ARRGET => {
-                let idx = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
-                let arr = typed_pop!(cur_ptr, env, inst, RuntimeValue::Arr);
-                let val = arr.get(idx as usize);
-                env.runtime_stack.push(val);
-            }
-            RuntimeInstruction::ARRSET => {
-                let val = env.runtime_stack.pop();
-                let idx = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
-                let mut arr = typed_pop!(cur_ptr, env, inst, RuntimeValue::Arr);
-                arr.set(idx as usize, &val);
+                values.reverse();
+                let arr = Array::new_typed(et, &values);
+                arr.track(&env.live_elements);
                 env.runtime_stack.push(RuntimeValue::Arr(arr));
+            } else {
+                err_ret!(
+                    cur_ptr,
+                    RunloopErrData::InvalidType(InvalidTypeError {
+                        actual: at,
+                        expected: "array".to_owned()
+                    })
+                );
             }
-            RuntimeInstruction::RECGET => {
-                let idx = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
-                let rec = typed_pop!(cur_ptr, env, inst, RuntimeValue::Record);
-                let val = rec.get(idx as usize);
-                env.runtime_stack.push(val);
-            }
-            RuntimeInstruction::RECSET => {
-                let val = env.runtime_stack.pop();
-                let idx = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
-                let mut rec = typed_pop!(cur_ptr, env, inst, RuntimeValue::Record);
-                rec.set(idx as usize, &val);
-                env.runtime_stack.push(RuntimeValue::Record(rec));
-            }
-            RuntimeInstruction::ARRLEN => {
-                let arr = typed_pop!(cur_ptr, env, inst, RuntimeValue::Arr);
-                let len = RuntimeValue::Integer(arr.len() as u64);
-                env.runtime_stack.push(len);
-            }
-            RuntimeInstruction::MKARRTYPE => {
-                let len = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
-                let vt = typed_pop!(cur_ptr, env, inst, RuntimeValue::Type);
-                let at = ArrayType::new(vt, len as usize);
-                let at: RuntimeValue = RuntimeValue::Type(RuntimeType::Arr(Box::new(at)));
-                env.runtime_stack.push(at);
-            }
-            RuntimeInstruction::MKRECTYPE => {
-                let mut len = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
-                let mut vt: Vec<RuntimeType> = Vec::with_capacity(len as usize);
-                while len > 0 {
+        }
+        RuntimeInstruction::NEWREC => {
+            let rt = typed_pop!(cur_ptr, env, inst, RuntimeValue::Type);
+            if let RuntimeType::Record(rt) = rt {
+                let len = rt.len();
+                check_elements(env, len, cur_ptr)?;
+                let mut values = Vec::<RuntimeValue>::with_capacity(len);
+                for _ in 0..len {
                     let val = stack_pop!(cur_ptr, env, inst);
-                    match val {
-                        RuntimeValue::Type(rt) => vt.insert(0, rt),
-                        _ => {
-                            err_ret!(
-                                cur_ptr,
-                                RunloopErrData::InvalidType(InvalidTypeError {
-                                    actual: val.get_type(),
-                                    expected: "type".to_owned()
-                                })
-                            );
-                        }
-                    }
-                    len -= 1;
+                    values.insert(0, val);
                 }
-                let rt = RecordType::new(&vt);
-                let rt = RuntimeValue::Type(RuntimeType::Record(Box::new(rt)));
-                env.runtime_stack.push(rt);
-            }
-            RuntimeInstruction::I2B => {
-                let val = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
-                env.runtime_stack.push(crate::rv_bool!(val != 0));
+                let rc = Record::new_typed(*rt, &values);
+                rc.track(&env.live_elements);
+                env.runtime_stack.push(RuntimeValue::Record(rc));
+            } else {
+                err_ret!(
+                    cur_ptr,
+                    RunloopErrData::InvalidType(InvalidTypeError {
+                        actual: rt,
+                        expected: "record".to_owned()
+                    })
+                );
             }
-            RuntimeInstruction::B2I => {
-                let val = typed_pop!(cur_ptr, env, inst, RuntimeValue::Logical);
-                env.runtime_stack
-                    .push(crate::rv_int!(if val { 1 } else { 0 }));
+        }
+        RuntimeInstruction::ARRGET => {
+            let idx = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
+            let arr = typed_pop!(cur_ptr, env, inst, RuntimeValue::Arr);
+            let val = arr.get(idx as usize);
+            env.runtime_stack.push(val);
+        }
+        RuntimeInstruction::ARRSET => {
+            let val = env.runtime_stack.pop();
+            let idx = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
+            let mut arr = typed_pop!(cur_ptr, env, inst, RuntimeValue::Arr);
+            arr.set(idx as usize, &val);
+            env.runtime_stack.push(RuntimeValue::Arr(arr));
+        }
+        RuntimeInstruction::RECGET => {
+            let idx = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
+            let rec = typed_pop!(cur_ptr, env, inst, RuntimeValue::Record);
+            let val = rec.get(idx as usize);
+            env.runtime_stack.push(val);
+        }
+        RuntimeInstruction::RECSET => {
+            let val = env.runtime_stack.pop();
+            let idx = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
+            let mut rec = typed_pop!(cur_ptr, env, inst, RuntimeValue::Record);
+            rec.set(idx as usize, &val);
+            env.runtime_stack.push(RuntimeValue::Record(rec));
+        }
+        RuntimeInstruction::ARRLEN => {
+            let arr = typed_pop!(cur_ptr, env, inst, RuntimeValue::Arr);
+            let len = RuntimeValue::Integer(arr.len() as u64);
+            env.runtime_stack.push(len);
+        }
+        RuntimeInstruction::MKARRTYPE => {
+            let len = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
+            let vt = typed_pop!(cur_ptr, env, inst, RuntimeValue::Type);
+            if let Some(max) = env.limits.max_live_elements {
+                if len > max as u64 {
+                    err_ret!(cur_ptr, RunloopErrData::ElementLimitExceeded(max));
+                }
             }
-            RuntimeInstruction::I2F => {
-                let val = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
-                env.runtime_stack.push(crate::rv_flt!(val as f64));
+            let at = ArrayType::new(vt, len as usize);
+            let at: RuntimeValue = RuntimeValue::Type(RuntimeType::Arr(Box::new(at)));
+            env.runtime_stack.push(at);
+        }
+        RuntimeInstruction::MKRECTYPE => {
+            let mut len = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
+            let cap = std::cmp::min(len as usize, env.runtime_stack.len());
+            let mut vt: Vec<RuntimeType> = Vec::with_capacity(cap);
+            while len > 0 {
+                let val = stack_pop!(cur_ptr, env, inst);
+                match val {
+                    RuntimeValue::Type(rt) => vt.insert(0, rt),
+                    _ => {
+                        err_ret!(
+                            cur_ptr,
+                            RunloopErrData::InvalidType(InvalidTypeError {
+                                actual: val.get_type(),
+                                expected: "type".to_owned()
+                            })
+                        );
+                    }
+                }
+                len -= 1;
             }
-            RuntimeInstruction::F2I => {
-                let val = typed_pop!(cur_ptr, env, inst, RuntimeValue::Float);
-                env.runtime_stack.push(crate::rv_int!(val as u64));
+            let rt = RecordType::new(&vt);
+            let rt = RuntimeValue::Type(RuntimeType::Record(Box::new(rt)));
+            env.runtime_stack.push(rt);
+        }
+        RuntimeInstruction::I2B => {
+            let val = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
+            env.runtime_stack.push(crate::rv_bool!(val != 0));
+        }
+        RuntimeInstruction::B2I => {
+            let val = typed_pop!(cur_ptr, env, inst, RuntimeValue::Logical);
+            env.runtime_stack
+                .push(crate::rv_int!(if val { 1 } else { 0 }));
+        }
+        RuntimeInstruction::I2F => {
+            let val = typed_pop!(cur_ptr, env, inst, RuntimeValue::Integer);
+            env.runtime_stack.push(crate::rv_flt!(val as f64));
+        }
+        RuntimeInstruction::F2I => {
+            let val = typed_pop!(cur_ptr, env, inst, RuntimeValue::Float);
+            env.runtime_stack.push(crate::rv_int!(val as u64));
+        }
+    }
+
+    check_stack_depth(env, cur_ptr)?;
+    Ok(None)
+}
+
+fn check_elements(env: &Environment, len: usize, cur_ptr: usize) -> RunloopResult {
+    if let Some(max) = env.limits.max_live_elements {
+        if env.live_elements.live().saturating_add(len) > max {
+            err_ret!(cur_ptr, RunloopErrData::ElementLimitExceeded(max));
+        }
+    }
+    Ok(())
+}
+
+fn check_stack_depth(env: &Environment, cur_ptr: usize) -> RunloopResult {
+    if let Some(max) = env.limits.max_stack_depth {
+        if env.runtime_stack.len() > max {
+            err_ret!(cur_ptr, RunloopErrData::ValueStackLimitExceeded(max));
+        }
+    }
+    Ok(())
+}
+
+fn push_frame(f: &RuntimeCallable, env: &mut Environment, cur_ptr: usize) -> RunloopResult {
+    if env.unwinder.len() >= env.max_call_depth {
+        err_ret!(cur_ptr, RunloopErrData::StackOverflow(env.max_call_depth));
+    }
+    env.unwinder.push_frame(f);
+    notify_entry(env, f);
+    Ok(())
+}
+
+fn pop_frame(env: &mut Environment) {
+    if !env.observers.is_empty() {
+        let f = env.unwinder.top().get_function();
+        let depth = env.unwinder.len();
+        for (_, observer) in env.observers.iter_mut() {
+            observer.function_exit(&f, depth);
+        }
+    }
+    env.unwinder.pop_frame();
+}
+
+fn notify_entry(env: &mut Environment, f: &RuntimeCallable) {
+    if !env.observers.is_empty() {
+        let depth = env.unwinder.len();
+        for (_, observer) in env.observers.iter_mut() {
+            observer.function_entry(f, depth);
+        }
+    }
+}
+
+fn notify_error(env: &mut Environment, result: RunloopResult) -> RunloopResult {
+    if let Err(err) = &result {
+        if !is_suspension(err) {
+            for (_, observer) in env.observers.iter_mut() {
+                observer.on_error(err, &env.unwinder);
             }
         }
     }
+    result
 }
 
+// bytecode-to-bytecode calls do not recurse on the native stack; each call
+// pushes a Frame on the unwinder, which saves the caller's ip and slots
 pub fn run_loop(callable: &RuntimeCallable, env: &mut Environment) -> RunloopResult {
-    env.unwinder.push_frame(callable);
+    discard_suspended(env);
+    let base = env.unwinder.len();
+    let pushed = push_frame(callable, env, 0);
+    notify_error(env, pushed)?;
+    run_frames(env, base)
+}
 
-    let result = match &callable.f.content {
-        either::Either::Left(f) => {
-            let ctx = BytecodeContext {
-                m: &callable.module(),
-                b: f.body(),
-            };
+// a new run started instead of resuming abandons the suspended one
+fn discard_suspended(env: &mut Environment) {
+    if let Some(base) = env.suspended_base.take() {
+        env.unwinder.split_off(base);
+    }
+}
 
-            bytecode_run_loop(&ctx, env)
-        }
-        either::Either::Right(f) => f.call(env),
-    };
+// continues a run that stopped with BudgetExhausted or Paused; the top frame
+// restarts at the instruction it did not get to execute
+pub fn resume(env: &mut Environment) -> RunloopResult {
+    match env.suspended_base.take() {
+        Some(base) => run_frames(env, base),
+        None => Ok(()),
+    }
+}
+
+pub fn run_loop_resumable(
+    callable: &RuntimeCallable,
+    env: &mut Environment,
+) -> Result<RunloopStatus, RunloopError> {
+    discard_suspended(env);
+    let base = env.unwinder.len();
+    let pushed = push_frame(callable, env, 0);
+    notify_error(env, pushed)?;
+    run_frames_resumable(env, base)
+}
 
-    match result {
-        Ok(_) => {
-            env.unwinder.pop_frame();
-            result
+// the run's values go back on top of whatever the stack holds by then
+pub fn resume_run(run: SuspendedRun, env: &mut Environment) -> Result<RunloopStatus, RunloopError> {
+    let base = env.unwinder.len();
+    env.unwinder.restore(run.frames);
+    env.runtime_stack.values.extend(run.stack);
+    run_frames_resumable(env, base)
+}
+
+fn run_frames_resumable(env: &mut Environment, base: usize) -> Result<RunloopStatus, RunloopError> {
+    match run_frames(env, base) {
+        Ok(_) => Ok(RunloopStatus::Finished),
+        Err(err) if is_suspension(&err) => {
+            env.suspended_base = None;
+            let frames = env.unwinder.split_off(base);
+            let stack = std::mem::take(&mut env.runtime_stack.values);
+            Ok(RunloopStatus::Suspended(SuspendedRun { frames, stack }))
         }
         Err(err) => Err(err),
     }
 }
+
+fn is_suspension(err: &RunloopError) -> bool {
+    matches!(
+        err.data,
+        RunloopErrData::BudgetExhausted | RunloopErrData::Paused
+    )
+}
+
+fn run_frames(env: &mut Environment, base: usize) -> RunloopResult {
+    env.active_runs += 1;
+    let result = run_frames_impl(env, base);
+    env.active_runs -= 1;
+    notify_error(env, result)
+}
+
+fn run_frames_impl(env: &mut Environment, base: usize) -> RunloopResult {
+    loop {
+        let callable = env.unwinder.top().get_function();
+        let exit = match &callable.f.content {
+            either::Either::Left(f) => {
+                let ctx = BytecodeContext {
+                    f: &callable,
+                    m: &callable.module(),
+                    c: f.code(),
+                };
+
+                let frame = env.unwinder.top_mut();
+                let mut ip = frame.resume_at;
+                let mut slots = std::mem::take(&mut frame.slots);
+                let result = bytecode_run_loop(&ctx, env, &mut ip, &mut slots);
+                let frame = env.unwinder.top_mut();
+                frame.resume_at = ip;
+                frame.slots = slots;
+                match result {
+                    Ok(exit) => exit,
+                    Err(err) => {
+                        frame.set_ip(err.cur_ptr);
+                        if is_suspension(&err) {
+                            // resuming could not bring back the Rust frame of
+                            // the native that started this run
+                            if env.active_runs > 1 {
+                                env.skip_observers = false;
+                                err_ret!(err.cur_ptr, RunloopErrData::SuspendedInNative);
+                            }
+                            env.suspended_base = Some(base);
+                        }
+                        return Err(err);
+                    }
+                }
+            }
+            either::Either::Right(f) => {
+                if let Some(signature) = f.signature() {
+                    signature.check_params(env)?;
+                }
+                f.call(env)?;
+                check_stack_depth(env, 0)?;
+                FrameExit::Return
+            }
+        };
+
+        match exit {
+            FrameExit::Return => {
+                pop_frame(env);
+                if env.unwinder.len() == base {
+                    return Ok(());
+                }
+            }
+            FrameExit::Call(f) => {
+                let cur_ptr = env.unwinder.top().get_ip().unwrap_or_default();
+                push_frame(&f, env, cur_ptr)?;
+            }
+            FrameExit::TailCall(f) => {
+                pop_frame(env);
+                env.unwinder.push_frame(&f);
+                notify_entry(env, &f);
+            }
+        }
+    }
+}
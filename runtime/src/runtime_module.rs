@@ -1,12 +1,17 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 use either::Either;
 
 use crate::{
-    bytecode::Bytecode,
     environ::Environment,
+    instruction_stream::InstructionStream,
     intern_value::InternValue,
     module_definition::{FunctionDef, ModuleDef},
+    native_fn::{ClosureCallable, NativeFn, NativeSignature},
     runloop::RunloopResult,
     types::typedef::TypeDef,
 };
@@ -14,6 +19,12 @@ use crate::{
 pub trait NativeCallable {
     fn call(&self, env: &mut Environment) -> RunloopResult;
     fn name(&self) -> String;
+
+    // natives that describe their operands are checked before each call; the
+    // results are documentation only and are not checked after it
+    fn signature(&self) -> Option<&NativeSignature> {
+        None
+    }
 }
 
 impl std::fmt::Debug for dyn NativeCallable {
@@ -25,14 +36,19 @@ impl std::fmt::Debug for dyn NativeCallable {
 #[derive(Debug)]
 pub(crate) struct RuntimeBytecodeFunctionImpl {
     pub(crate) name: String,
-    pub(crate) body: Bytecode,
+    pub(crate) arity: Option<usize>,
+    pub(crate) code: InstructionStream,
+    // kept for tools that address code by block label
+    pub(crate) labels: Vec<(String, usize)>,
 }
 
 impl From<FunctionDef> for RuntimeBytecodeFunctionImpl {
     fn from(value: FunctionDef) -> Self {
         Self {
             name: value.name().clone(),
-            body: value.body().clone(),
+            arity: value.arity(),
+            code: InstructionStream::from(value.body()),
+            labels: value.labels().cloned().collect(),
         }
     }
 }
@@ -47,8 +63,8 @@ impl RuntimeBytecodeFunction {
         self.f.name.clone()
     }
 
-    pub(crate) fn body(&self) -> &Bytecode {
-        &self.f.body
+    pub(crate) fn code(&self) -> &InstructionStream {
+        &self.f.code
     }
 }
 
@@ -118,6 +134,33 @@ impl RuntimeCallable {
     pub fn module(&self) -> RuntimeModule {
         self.f.owner.clone()
     }
+
+    pub fn arity(&self) -> Option<usize> {
+        match &self.f.content {
+            Either::Left(f) => f.f.arity,
+            Either::Right(f) => f.signature().map(|s| s.params.len()),
+        }
+    }
+
+    // the bytecode offset of a block label; natives have none
+    pub fn find_label(&self, name: &str) -> Option<usize> {
+        match &self.f.content {
+            Either::Left(f) => {
+                f.f.labels
+                    .iter()
+                    .find(|(label, _)| label == name)
+                    .map(|(_, offset)| *offset)
+            }
+            Either::Right(_) => None,
+        }
+    }
+
+    pub fn signature(&self) -> Option<&NativeSignature> {
+        match &self.f.content {
+            Either::Left(_) => None,
+            Either::Right(f) => f.signature(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -181,7 +224,14 @@ struct RuntimeModuleImpl {
     name: String,
     functions: HashMap<String, RuntimeCallable>,
     named_types: HashMap<String, RuntimeTypeDef>,
+    exported_functions: HashSet<String>,
+    exported_types: HashSet<String>,
     intern_values: Vec<Rc<InternValue>>,
+    call_targets: Vec<String>,
+    // call targets by index, as resolved against the modules of the
+    // Environment generation in targets_generation
+    resolved_targets: Vec<Option<RuntimeCallable>>,
+    targets_generation: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -201,6 +251,9 @@ impl RuntimeModule {
         md.interned_values().for_each(|i| {
             this.add_intern_value(i);
         });
+        md.call_targets().for_each(|t| {
+            this.add_call_target(t);
+        });
         this
     }
 
@@ -210,38 +263,79 @@ impl RuntimeModule {
                 name: name.to_string(),
                 functions: HashMap::new(),
                 named_types: HashMap::new(),
+                exported_functions: HashSet::new(),
+                exported_types: HashSet::new(),
                 intern_values: vec![],
+                call_targets: vec![],
+                resolved_targets: vec![],
+                targets_generation: 0,
             })),
         }
     }
 
     pub fn add_named_type(&mut self, r: &TypeDef) -> RuntimeTypeDef {
         let t = RuntimeTypeDef::from_tdef(self, r.clone());
-        self.m
-            .borrow_mut()
-            .named_types
-            .insert(r.name().to_string(), t.clone());
+        let mut m = self.m.borrow_mut();
+        m.named_types.insert(r.name().to_string(), t.clone());
+        if r.visibility().is_public() {
+            m.exported_types.insert(r.name().to_string());
+        } else {
+            m.exported_types.remove(r.name());
+        }
         t
     }
 
     pub fn add_function_fdef(&mut self, f: &FunctionDef) -> RuntimeCallable {
+        let visibility = f.visibility();
         let f = RuntimeCallable::from_fdef(self, f.clone());
-        self.m
-            .borrow_mut()
-            .functions
-            .insert(f.name().to_string(), f.clone());
+        let mut m = self.m.borrow_mut();
+        m.functions.insert(f.name().to_string(), f.clone());
+        if visibility.is_public() {
+            m.exported_functions.insert(f.name().to_string());
+        } else {
+            m.exported_functions.remove(&f.name());
+        }
         f
     }
 
     pub fn add_function_native(&mut self, f: Box<dyn NativeCallable>) -> RuntimeCallable {
         let f = RuntimeCallable::from_native(self, f);
-        self.m
-            .borrow_mut()
-            .functions
-            .insert(f.name().to_string(), f.clone());
+        let mut m = self.m.borrow_mut();
+        m.functions.insert(f.name().to_string(), f.clone());
+        m.exported_functions.insert(f.name().to_string());
         f
     }
 
+    // registers a Rust function as a native; arguments are popped and
+    // converted to its parameter types, and its result is pushed back
+    pub fn add_native_fn<Args: 'static, F: NativeFn<Args> + 'static>(
+        &mut self,
+        name: &str,
+        f: F,
+    ) -> RuntimeCallable {
+        self.add_function_native(Box::new(ClosureCallable::new(name, f)))
+    }
+
+    pub fn is_function_exported(&self, name: &str) -> bool {
+        self.m.borrow().exported_functions.contains(name)
+    }
+
+    pub fn is_named_type_exported(&self, name: &str) -> bool {
+        self.m.borrow().exported_types.contains(name)
+    }
+
+    pub fn function_names(&self) -> Vec<String> {
+        let mut names = self
+            .m
+            .borrow()
+            .functions
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
     pub fn find_function(&self, name: &str) -> Option<RuntimeCallable> {
         self.m.borrow().functions.get(name).cloned()
     }
@@ -260,6 +354,38 @@ impl RuntimeModule {
         self.m.borrow().intern_values.get(idx as usize).cloned()
     }
 
+    pub fn add_call_target(&mut self, name: &str) -> usize {
+        let mut m = self.m.borrow_mut();
+        m.call_targets.push(name.to_owned());
+        m.call_targets.len() - 1
+    }
+
+    pub fn get_call_target_name(&self, idx: u16) -> Option<String> {
+        self.m.borrow().call_targets.get(idx as usize).cloned()
+    }
+
+    pub(crate) fn resolved_target(&self, idx: u16, generation: u64) -> Option<RuntimeCallable> {
+        let m = self.m.borrow();
+        if m.targets_generation != generation {
+            return None;
+        }
+        m.resolved_targets.get(idx as usize).cloned().flatten()
+    }
+
+    // resolutions made for another generation are dropped first
+    pub(crate) fn set_resolved_target(&self, idx: u16, generation: u64, f: RuntimeCallable) {
+        let mut m = self.m.borrow_mut();
+        if m.targets_generation != generation {
+            m.resolved_targets.clear();
+            m.targets_generation = generation;
+        }
+        let idx = idx as usize;
+        if m.resolved_targets.len() <= idx {
+            m.resolved_targets.resize(idx + 1, None);
+        }
+        m.resolved_targets[idx] = Some(f);
+    }
+
     pub fn name(&self) -> String {
         self.m.borrow().name.clone()
     }
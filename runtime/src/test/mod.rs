@@ -3,9 +3,14 @@ use crate::{
     bytecode::Bytecode,
     environ::Environment,
     iv_str,
-    module_definition::{FunctionDef, ModuleDef},
+    limits::Limits,
+    module_definition::{FunctionDef, ModuleDef, Visibility},
+    observer::{ExecutionObserver, InstructionEvent, ObserverAction},
     opcodes::Opcode,
-    runloop::{run_loop, RunloopErrData, RunloopResult},
+    runloop::{
+        resume, resume_run, run_loop, run_loop_resumable, RunloopErrData, RunloopError,
+        RunloopResult, RunloopStatus,
+    },
     runtime_module::{NativeCallable, RuntimeModule},
     rv_int,
     types::{array::ArrayType, record::RecordType, RuntimeType},
@@ -522,7 +527,7 @@ fn test_runloop_slots() {
 fn test_array_typed() {
     let mut a = Array::new_typed(
         RuntimeType::Logical,
-        &vec![
+        &[
             RuntimeValue::Logical(false),
             RuntimeValue::Logical(true),
             RuntimeValue::Logical(false),
@@ -555,7 +560,7 @@ fn test_array_typed() {
 
 #[test]
 fn test_array_inferred() {
-    let mut a = Array::new_inferred(&vec![
+    let mut a = Array::new_inferred(&[
         RuntimeValue::Logical(false),
         RuntimeValue::Logical(true),
         RuntimeValue::Logical(false),
@@ -630,7 +635,7 @@ fn test_newarr_instruction() {
         assert_eq!(RuntimeValue::Integer(4), arr.get(3));
         assert_eq!(RuntimeValue::Integer(6), arr.get(4));
     } else {
-        assert!(false, "not an array on stack");
+        panic!("not an array on stack");
     }
 }
 
@@ -780,3 +785,1625 @@ fn test_runloop_err() {
     assert_eq!(env.print_unwind(), "module.main:1");
     assert_eq!(rl.cur_ptr, 1);
 }
+
+#[test]
+fn test_private_function_lookup() {
+    let mut ret = Bytecode::default();
+    ret.write_u8(u8::from(Opcode::RET));
+    // functions are private unless marked otherwise
+    let hidden = FunctionDef::new("hidden", ret.clone());
+    let mut shown = FunctionDef::new("shown", ret);
+    shown.set_visibility(Visibility::Public);
+
+    let mut lib = ModuleDef::new("com.lib");
+    lib.add_function(hidden);
+    lib.add_function(shown);
+
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::FLOOKUP);
+    block.append_instruction(InstructionDef::CALL);
+    block.append_instruction(InstructionDef::PUSH(1));
+    block.append_instruction(InstructionDef::FLOOKUP);
+    block.append_instruction(InstructionDef::CALL);
+    block.append_instruction(InstructionDef::RET);
+    let main = builder.generate();
+
+    let mut md = ModuleDef::new("com.app");
+    md.add_interned_value(iv_str!("com.lib.shown"));
+    md.add_interned_value(iv_str!("com.lib.hidden"));
+    md.add_function(main);
+
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&lib));
+    env.add_module(RuntimeModule::from(&md));
+
+    assert!(env.lookup_function("com.lib.hidden").is_some());
+
+    let main = env
+        .lookup_function("com.app.main")
+        .expect("main function missing");
+    let rl = run_loop(&main, &mut env).unwrap_err();
+    assert_eq!(
+        rl.data,
+        RunloopErrData::PrivateFunction("com.lib.hidden".to_owned())
+    );
+    assert_eq!(rl.cur_ptr, 8);
+}
+
+#[test]
+fn test_visibility_migration() {
+    use crate::types::typedef::TypeDef;
+
+    // items built in Rust start out private, like unmarked assembler items
+    let mut ret = Bytecode::default();
+    ret.write_u8(u8::from(Opcode::RET));
+    let mut f = FunctionDef::new("f", ret);
+    assert_eq!(Visibility::Private, f.visibility());
+    let t = TypeDef::new("t", &RuntimeType::Integer);
+    assert_eq!(Visibility::Private, t.visibility());
+
+    // marking them public survives a round trip through the module format
+    f.set_visibility(Visibility::Public);
+    let mut md = ModuleDef::new("com.lib");
+    md.add_function(f);
+    let read = ModuleDef::from_bytes(&md.to_bytes().unwrap()).unwrap();
+    assert!(RuntimeModule::from(&read).is_function_exported("f"));
+}
+
+#[test]
+fn test_private_lookup_same_module() {
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::FLOOKUP);
+    block.append_instruction(InstructionDef::CALL);
+    block.append_instruction(InstructionDef::PUSH(1));
+    block.append_instruction(InstructionDef::TLOOKUP);
+    block.append_instruction(InstructionDef::RET);
+    let main = builder.generate();
+
+    let mut helper = Builder::new("helper");
+    let mut block = helper.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(2));
+    block.append_instruction(InstructionDef::RET);
+    let mut helper = helper.generate();
+    helper.set_visibility(Visibility::Private);
+
+    let mut tdef = RuntimeType::Integer.to_typedef("number");
+    tdef.set_visibility(Visibility::Private);
+
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(iv_str!("module.helper"));
+    md.add_interned_value(iv_str!("module.number"));
+    md.add_interned_value(crate::intern_value::InternValue::Integer(42));
+    md.add_function(main);
+    md.add_function(helper);
+    md.add_named_type(&tdef);
+
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&md));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    assert!(run_loop(&main, &mut env).is_ok());
+    assert_eq!(RuntimeValue::Type(RuntimeType::Integer), env.pop_value());
+    assert_eq!(rv_int!(42), env.pop_value());
+}
+
+#[test]
+fn test_private_type_lookup() {
+    let mut tdef = RuntimeType::Integer.to_typedef("number");
+    tdef.set_visibility(Visibility::Private);
+    let mut lib = ModuleDef::new("com.lib");
+    lib.add_named_type(&tdef);
+
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::TLOOKUP);
+    block.append_instruction(InstructionDef::RET);
+    let main = builder.generate();
+
+    let mut md = ModuleDef::new("com.app");
+    md.add_interned_value(iv_str!("com.lib.number"));
+    md.add_function(main);
+
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&lib));
+    env.add_module(RuntimeModule::from(&md));
+
+    assert!(env.lookup_named_type("com.lib.number").is_some());
+
+    let main = env
+        .lookup_function("com.app.main")
+        .expect("main function missing");
+    let rl = run_loop(&main, &mut env).unwrap_err();
+    assert_eq!(
+        rl.data,
+        RunloopErrData::PrivateType("com.lib.number".to_owned())
+    );
+}
+
+#[test]
+fn test_calldirect() {
+    let mut callee = Builder::new("callee");
+    let mut block = callee.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::ADD);
+    block.append_instruction(InstructionDef::RET);
+    let callee = callee.generate();
+
+    let mut main = Builder::new("main");
+    let mut block = main.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::CALLDIRECT(0));
+    block.append_instruction(InstructionDef::CALLDIRECT(0));
+    block.append_instruction(InstructionDef::RET);
+    let main = main.generate();
+
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(crate::intern_value::InternValue::Integer(5));
+    assert_eq!(0, md.add_call_target("module.callee"));
+    assert_eq!(0, md.add_call_target("module.callee"));
+    md.add_function(main);
+    md.add_function(callee);
+
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&md));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    assert!(run_loop(&main, &mut env).is_ok());
+    assert_eq!(rv_int!(15), env.pop_value());
+    assert!(env.is_stack_empty());
+}
+
+#[test]
+fn test_calldirect_errors() {
+    let mut main = Builder::new("main");
+    let mut block = main.append_block("entry");
+    block.append_instruction(InstructionDef::CALLDIRECT(1));
+    block.append_instruction(InstructionDef::RET);
+    let main = main.generate();
+
+    let mut other = Builder::new("other");
+    let mut block = other.append_block("entry");
+    block.append_instruction(InstructionDef::CALLDIRECT(0));
+    block.append_instruction(InstructionDef::RET);
+    let other = other.generate();
+
+    let mut md = ModuleDef::new("module");
+    md.add_call_target("module.nothere");
+    md.add_function(main);
+    md.add_function(other);
+
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&md));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    let rl = run_loop(&main, &mut env).unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::MissingCallTarget(1));
+
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&md));
+    let other = env
+        .lookup_function("module.other")
+        .expect("other function missing");
+    let rl = run_loop(&other, &mut env).unwrap_err();
+    assert_eq!(
+        rl.data,
+        RunloopErrData::MissingFunction("module.nothere".to_owned())
+    );
+}
+
+#[test]
+fn test_instruction_stream() {
+    use crate::instruction_runtime::RuntimeInstruction;
+    use crate::instruction_stream::InstructionStream;
+
+    let mut builder = Builder::new("main");
+    let mut entry = builder.append_block("entry");
+    let mut exit = builder.append_block("exit");
+    entry.append_instruction(InstructionDef::PUSH(0));
+    entry.append_instruction(InstructionDef::DUP);
+    entry.append_instruction(InstructionDef::JTRUE(exit.clone()));
+    entry.append_instruction(InstructionDef::JUMP(entry.clone()));
+    exit.append_instruction(InstructionDef::RET);
+    let main = builder.generate();
+
+    let stream = InstructionStream::from(main.body());
+    assert_eq!(5, stream.len());
+    assert!(!stream.is_invalid());
+    assert_eq!(Some(&RuntimeInstruction::PUSH(0)), stream.get(0));
+    assert_eq!(Some(&RuntimeInstruction::JTRUE(4)), stream.get(2));
+    assert_eq!(Some(&RuntimeInstruction::JUMP(0)), stream.get(3));
+    assert_eq!(None, stream.get(5));
+    assert_eq!(Some(RuntimeInstruction::JTRUE(10)), stream.original(2));
+    assert_eq!(Some(RuntimeInstruction::JUMP(0)), stream.original(3));
+    assert_eq!(Some(RuntimeInstruction::RET), stream.original(4));
+    assert_eq!(None, stream.original(5));
+    assert_eq!(0, stream.offset(0));
+    assert_eq!(3, stream.offset(1));
+    assert_eq!(4, stream.offset(2));
+    assert_eq!(10, stream.offset(4));
+    assert_eq!(11, stream.offset(5));
+    assert_eq!(Some(4), stream.index_of_offset(10));
+    assert_eq!(None, stream.index_of_offset(5));
+}
+
+#[test]
+fn test_fall_off_function_end() {
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::NOP);
+    block.append_instruction(InstructionDef::PUSH(0));
+
+    let main = builder.generate();
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(crate::intern_value::InternValue::Integer(5));
+    md.add_function(main);
+
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&md));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    let rl = run_loop(&main, &mut env).unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::InstrutionOutOfBounds);
+    assert_eq!(rl.cur_ptr, 4);
+    assert_eq!(env.print_unwind(), "module.main:4");
+}
+
+#[test]
+fn test_tailcall_native() {
+    struct NativeReturn42 {}
+    impl NativeCallable for NativeReturn42 {
+        fn call(&self, env: &mut Environment) -> RunloopResult {
+            env.push_value(rv_int!(42));
+            Ok(())
+        }
+
+        fn name(&self) -> String {
+            "fortytwo".to_owned()
+        }
+    }
+
+    let mut native_module = RuntimeModule::new("tukun");
+    native_module.add_function_native(Box::new(NativeReturn42 {}));
+
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::TAILCALL(0));
+
+    let mut md = ModuleDef::new("module");
+    md.add_call_target("tukun.fortytwo");
+    md.add_function(builder.generate());
+
+    let mut env = Environment::default();
+    env.add_module(native_module);
+    env.add_module(RuntimeModule::from(&md));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    assert!(run_loop(&main, &mut env).is_ok());
+    assert_eq!(rv_int!(42), env.pop_value());
+    assert!(env.print_unwind().is_empty());
+}
+
+fn recursive_countdown_module(n: u64) -> ModuleDef {
+    let mut md = ModuleDef::new("module");
+    let zero = md.add_interned_value(crate::intern_value::InternValue::Integer(0)) as u16;
+    let one = md.add_interned_value(crate::intern_value::InternValue::Integer(1)) as u16;
+    let count = md.add_interned_value(crate::intern_value::InternValue::Integer(n)) as u16;
+    let down = md.add_call_target("module.down") as u16;
+
+    let mut builder = Builder::new("down");
+    let mut entry = builder.append_block("entry");
+    let mut done = builder.append_block("done");
+    entry.append_instruction(InstructionDef::DUP);
+    entry.append_instruction(InstructionDef::PUSH(zero));
+    entry.append_instruction(InstructionDef::EQ);
+    entry.append_instruction(InstructionDef::JTRUE(done.clone()));
+    entry.append_instruction(InstructionDef::PUSH(one));
+    entry.append_instruction(InstructionDef::SWAP);
+    entry.append_instruction(InstructionDef::SUB);
+    entry.append_instruction(InstructionDef::CALLDIRECT(down));
+    entry.append_instruction(InstructionDef::RET);
+    done.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let mut builder = Builder::new("main");
+    let mut entry = builder.append_block("entry");
+    entry.append_instruction(InstructionDef::PUSH(count));
+    entry.append_instruction(InstructionDef::CALLDIRECT(down));
+    entry.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    md
+}
+
+#[test]
+fn test_deep_recursion() {
+    let mut env = Environment::default();
+    env.set_max_call_depth(500_000);
+    env.add_module(RuntimeModule::from(&recursive_countdown_module(400_000)));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    assert!(run_loop(&main, &mut env).is_ok());
+    assert_eq!(rv_int!(0), env.pop_value());
+    assert!(env.is_stack_empty());
+    assert_eq!(0, env.call_depth());
+}
+
+#[test]
+fn test_stack_overflow() {
+    let mut env = Environment::default();
+    env.set_max_call_depth(100);
+    env.add_module(RuntimeModule::from(&recursive_countdown_module(1000)));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    let rl = run_loop(&main, &mut env).unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::StackOverflow(100));
+    assert_eq!(rl.cur_ptr, 13);
+    assert_eq!(100, env.call_depth());
+    assert!(env
+        .print_unwind()
+        .starts_with("module.down:13\nmodule.down:13"));
+}
+
+#[test]
+fn test_frame_slots_preserved_across_calls() {
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(crate::intern_value::InternValue::Integer(3));
+    md.add_interned_value(crate::intern_value::InternValue::Integer(4));
+    md.add_call_target("module.clobber");
+
+    let mut builder = Builder::new("clobber");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(1));
+    block.append_instruction(InstructionDef::TOSLOT(0));
+    block.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::TOSLOT(0));
+    block.append_instruction(InstructionDef::CALLDIRECT(0));
+    block.append_instruction(InstructionDef::FROMSLOT(0));
+    block.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&md));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    assert!(run_loop(&main, &mut env).is_ok());
+    assert_eq!(rv_int!(3), env.pop_value());
+    assert!(env.is_stack_empty());
+}
+
+fn countdown_loop_module(n: u64) -> ModuleDef {
+    let mut md = ModuleDef::new("module");
+    let zero = md.add_interned_value(crate::intern_value::InternValue::Integer(0)) as u16;
+    let one = md.add_interned_value(crate::intern_value::InternValue::Integer(1)) as u16;
+    let count = md.add_interned_value(crate::intern_value::InternValue::Integer(n)) as u16;
+
+    let mut builder = Builder::new("main");
+    let mut entry = builder.append_block("entry");
+    let mut lp = builder.append_block("loop");
+    let mut done = builder.append_block("done");
+    entry.append_instruction(InstructionDef::PUSH(count));
+    lp.append_instruction(InstructionDef::DUP);
+    lp.append_instruction(InstructionDef::PUSH(zero));
+    lp.append_instruction(InstructionDef::EQ);
+    lp.append_instruction(InstructionDef::JTRUE(done.clone()));
+    lp.append_instruction(InstructionDef::PUSH(one));
+    lp.append_instruction(InstructionDef::SWAP);
+    lp.append_instruction(InstructionDef::SUB);
+    lp.append_instruction(InstructionDef::JUMP(lp.clone()));
+    done.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    md
+}
+
+#[test]
+fn test_instruction_budget() {
+    let mut env = Environment::default();
+    env.set_instruction_budget(Some(9));
+    env.add_module(RuntimeModule::from(&countdown_loop_module(100)));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    let rl = run_loop(&main, &mut env).unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::BudgetExhausted);
+    // PUSH, then one full loop iteration
+    assert_eq!(rl.cur_ptr, 3);
+    assert_eq!(Some(0), env.instruction_budget());
+    assert!(env.is_suspended());
+    assert_eq!("module.main:3", env.print_unwind());
+
+    let rl = resume(&mut env).unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::BudgetExhausted);
+    assert_eq!(rl.cur_ptr, 3);
+
+    env.refuel(1000);
+    assert!(resume(&mut env).is_ok());
+    assert!(!env.is_suspended());
+    assert_eq!(Some(1000 - 99 * 8 - 5), env.instruction_budget());
+    assert_eq!(rv_int!(0), env.pop_value());
+    assert!(env.is_stack_empty());
+    assert_eq!(0, env.call_depth());
+}
+
+#[test]
+fn test_instruction_budget_across_calls() {
+    let mut env = Environment::default();
+    env.set_instruction_budget(Some(50));
+    env.add_module(RuntimeModule::from(&recursive_countdown_module(100)));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    let rl = run_loop(&main, &mut env).unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::BudgetExhausted);
+    assert!(env.call_depth() > 1);
+
+    while let Err(err) = resume(&mut env) {
+        assert_eq!(err.data, RunloopErrData::BudgetExhausted);
+        env.refuel(50);
+    }
+    assert_eq!(rv_int!(0), env.pop_value());
+    assert!(env.is_stack_empty());
+    assert_eq!(0, env.call_depth());
+}
+
+#[test]
+fn test_suspend_and_resume_run() {
+    let mut env = Environment::default();
+    env.set_instruction_budget(Some(50));
+    env.add_module(RuntimeModule::from(&recursive_countdown_module(100)));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    let run = match run_loop_resumable(&main, &mut env) {
+        Ok(RunloopStatus::Suspended(run)) => run,
+        _ => panic!("expected a suspended run"),
+    };
+    assert_eq!(0, env.call_depth());
+    assert!(!env.is_suspended());
+    assert!(run.depth() > 1);
+    assert_eq!("module.main", run.frames()[0].get_function().fullname());
+    assert_eq!(Some(3), run.frames()[0].get_ip());
+    assert_eq!("module.down", run.frames()[1].get_function().fullname());
+
+    assert!(env.is_stack_empty());
+    assert!(!run.stack().is_empty());
+
+    // other runs in between neither see nor disturb the suspended one
+    env.set_instruction_budget(None);
+    env.push_value(rv_int!(42));
+    assert_eq!(vec![rv_int!(0)], env.call("module.main", &[]).unwrap());
+    assert!(matches!(
+        resume_run(run, &mut env),
+        Ok(RunloopStatus::Finished)
+    ));
+    assert_eq!(rv_int!(0), env.pop_value());
+    assert_eq!(rv_int!(42), env.pop_value());
+    assert!(env.is_stack_empty());
+    assert_eq!(0, env.call_depth());
+}
+
+#[test]
+fn test_time_slice_runs() {
+    let mut envs = [Environment::default(), Environment::default()];
+    envs[0].add_module(RuntimeModule::from(&countdown_loop_module(1000)));
+    envs[1].add_module(RuntimeModule::from(&recursive_countdown_module(300)));
+
+    let mut runs = vec![];
+    for env in envs.iter_mut() {
+        env.set_instruction_budget(Some(0));
+        let main = env
+            .lookup_function("module.main")
+            .expect("main function missing");
+        match run_loop_resumable(&main, env) {
+            Ok(RunloopStatus::Suspended(run)) => runs.push(Some(run)),
+            _ => panic!("expected a suspended run"),
+        }
+    }
+
+    let mut slices = 0;
+    while runs.iter().any(|r| r.is_some()) {
+        for (env, slot) in envs.iter_mut().zip(runs.iter_mut()) {
+            if let Some(run) = slot.take() {
+                env.refuel(100);
+                match resume_run(run, env) {
+                    Ok(RunloopStatus::Suspended(run)) => *slot = Some(run),
+                    Ok(RunloopStatus::Finished) => {}
+                    Err(err) => panic!("unexpected error {err:?}"),
+                }
+            }
+        }
+        slices += 1;
+    }
+    assert!(slices > 10);
+
+    for env in envs.iter_mut() {
+        assert_eq!(rv_int!(0), env.pop_value());
+        assert!(env.is_stack_empty());
+        assert_eq!(0, env.call_depth());
+    }
+}
+
+fn make_array_module(count: usize, pop_between: bool) -> ModuleDef {
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(crate::intern_value::InternValue::Integer(5));
+
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    for i in 0..count {
+        if pop_between && i > 0 {
+            block.append_instruction(InstructionDef::POP);
+        }
+        for _ in 0..5 {
+            block.append_instruction(InstructionDef::PUSH(0));
+        }
+        block.append_instruction(InstructionDef::PUSH(0));
+        block.append_instruction(InstructionDef::TYPEOF);
+        block.append_instruction(InstructionDef::PUSH(0));
+        block.append_instruction(InstructionDef::MKARRTYPE);
+        block.append_instruction(InstructionDef::NEWARR);
+    }
+    block.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    md
+}
+
+fn run_main_with_limits(md: &ModuleDef, limits: Limits) -> (Environment, RunloopResult) {
+    let mut env = Environment::default();
+    env.set_limits(limits);
+    env.add_module(RuntimeModule::from(md));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    let result = run_loop(&main, &mut env);
+    (env, result)
+}
+
+#[test]
+fn test_element_limit() {
+    let limits = Limits {
+        max_live_elements: Some(8),
+        ..Default::default()
+    };
+
+    let (mut env, result) = run_main_with_limits(&make_array_module(3, true), limits);
+    assert!(result.is_ok());
+    assert_eq!(5, env.live_elements());
+    env.pop_value();
+    assert_eq!(0, env.live_elements());
+
+    let (env, result) = run_main_with_limits(&make_array_module(2, false), limits);
+    let rl = result.unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::ElementLimitExceeded(8));
+    assert_eq!(rl.cur_ptr, 47);
+    assert_eq!(5, env.live_elements());
+}
+
+#[test]
+fn test_element_limit_array_type() {
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(crate::intern_value::InternValue::Integer(u32::MAX as u64));
+
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::TYPEOF);
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::MKARRTYPE);
+    block.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let limits = Limits {
+        max_live_elements: Some(1000),
+        ..Default::default()
+    };
+    let (_, result) = run_main_with_limits(&md, limits);
+    let rl = result.unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::ElementLimitExceeded(1000));
+    assert_eq!(rl.cur_ptr, 7);
+}
+
+#[test]
+fn test_string_limit() {
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(iv_str!("short"));
+    md.add_interned_value(iv_str!("a much longer string"));
+
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::PUSH(1));
+    block.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let limits = Limits {
+        max_string_constant_bytes: Some(10),
+        ..Default::default()
+    };
+    let (env, result) = run_main_with_limits(&md, limits);
+    let rl = result.unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::StringLimitExceeded(10));
+    assert_eq!(rl.cur_ptr, 3);
+    assert_eq!(1, env.stack_len());
+}
+
+#[test]
+fn test_slot_limit() {
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(crate::intern_value::InternValue::Integer(1));
+
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    for i in 0..3 {
+        block.append_instruction(InstructionDef::PUSH(0));
+        block.append_instruction(InstructionDef::TOSLOT(i));
+    }
+    block.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let limits = Limits {
+        max_slots: Some(2),
+        ..Default::default()
+    };
+    let (_, result) = run_main_with_limits(&md, limits);
+    let rl = result.unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::SlotLimitExceeded(2));
+    assert_eq!(rl.cur_ptr, 15);
+
+    let (_, result) = run_main_with_limits(&md, Default::default());
+    assert!(result.is_ok());
+}
+
+#[test]
+fn test_invalid_fromslot() {
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::FROMSLOT(3));
+    block.append_instruction(InstructionDef::RET);
+    let mut md = ModuleDef::new("module");
+    md.add_function(builder.generate());
+
+    let (_, result) = run_main_with_limits(&md, Default::default());
+    let rl = result.unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::InvalidSlot(3));
+    assert_eq!(rl.cur_ptr, 0);
+}
+
+#[test]
+fn test_stack_depth_limit() {
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(crate::intern_value::InternValue::Integer(1));
+
+    let mut builder = Builder::new("main");
+    let mut entry = builder.append_block("entry");
+    let mut lp = builder.append_block("loop");
+    entry.append_instruction(InstructionDef::PUSH(0));
+    lp.append_instruction(InstructionDef::DUP);
+    lp.append_instruction(InstructionDef::JUMP(lp.clone()));
+    md.add_function(builder.generate());
+
+    let limits = Limits {
+        max_stack_depth: Some(100),
+        ..Default::default()
+    };
+    let (env, result) = run_main_with_limits(&md, limits);
+    let rl = result.unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::ValueStackLimitExceeded(100));
+    assert_eq!(rl.cur_ptr, 3);
+    assert_eq!(101, env.stack_len());
+}
+
+#[test]
+fn test_builder_labels() {
+    let md = countdown_loop_module(3);
+    let main = md.functions().find(|f| f.name() == "main").unwrap();
+    assert_eq!(Some(0), main.find_label("entry"));
+    assert_eq!(Some(3), main.find_label("loop"));
+    assert_eq!(Some(19), main.find_label("done"));
+    assert_eq!(None, main.find_label("missing"));
+}
+
+#[test]
+fn test_observer_pauses() {
+    struct BreakAt {
+        offset: usize,
+        hits: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+    impl ExecutionObserver for BreakAt {
+        fn before_instruction(&mut self, event: &InstructionEvent) -> ObserverAction {
+            assert_eq!("module.main", event.function.fullname());
+            assert_eq!(1, event.depth);
+            if event.offset == self.offset {
+                self.hits.set(self.hits.get() + 1);
+                ObserverAction::Pause
+            } else {
+                ObserverAction::Continue
+            }
+        }
+    }
+
+    let hits = std::rc::Rc::new(std::cell::Cell::new(0));
+    let mut env = Environment::default();
+    env.add_observer(Box::new(BreakAt {
+        offset: 19,
+        hits: hits.clone(),
+    }));
+    env.add_module(RuntimeModule::from(&countdown_loop_module(3)));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    let rl = run_loop(&main, &mut env).unwrap_err();
+    assert_eq!(rl.data, RunloopErrData::Paused);
+    assert_eq!(rl.cur_ptr, 19);
+    assert_eq!(1, hits.get());
+    assert_eq!(rv_int!(0), *env.stack_values().last().unwrap());
+
+    assert!(resume(&mut env).is_ok());
+    assert_eq!(1, hits.get());
+    assert_eq!(rv_int!(0), env.pop_value());
+    assert!(env.is_stack_empty());
+}
+
+#[derive(Default)]
+struct RecordingObserver {
+    events: std::rc::Rc<std::cell::RefCell<Vec<String>>>,
+}
+
+impl ExecutionObserver for RecordingObserver {
+    fn before_instruction(&mut self, event: &InstructionEvent) -> ObserverAction {
+        self.events.borrow_mut().push(format!(
+            "{}:{} {:?} [{}] {}",
+            event.function.fullname(),
+            event.offset,
+            event.instruction,
+            event.stack.len(),
+            event.depth
+        ));
+        ObserverAction::Continue
+    }
+
+    fn function_entry(&mut self, f: &crate::runtime_module::RuntimeCallable, depth: usize) {
+        self.events
+            .borrow_mut()
+            .push(format!("enter {} {}", f.fullname(), depth));
+    }
+
+    fn function_exit(&mut self, f: &crate::runtime_module::RuntimeCallable, depth: usize) {
+        self.events
+            .borrow_mut()
+            .push(format!("exit {} {}", f.fullname(), depth));
+    }
+
+    fn on_error(
+        &mut self,
+        err: &crate::runloop::RunloopError,
+        unwinder: &crate::unwinder::Unwinder,
+    ) {
+        self.events
+            .borrow_mut()
+            .push(format!("error {:?} {}", err.data, unwinder.len()));
+    }
+}
+
+#[test]
+fn test_observer_events() {
+    let observer = RecordingObserver::default();
+    let events = observer.events.clone();
+
+    let mut env = Environment::default();
+    let id = env.add_observer(Box::new(observer));
+    assert!(env.has_observers());
+    env.add_module(RuntimeModule::from(&recursive_countdown_module(1)));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    assert!(run_loop(&main, &mut env).is_ok());
+    assert_eq!(rv_int!(0), env.pop_value());
+    assert_eq!(
+        vec![
+            "enter module.main 1",
+            "module.main:0 PUSH(2) [0] 1",
+            "module.main:3 CALLDIRECT(0) [1] 1",
+            "enter module.down 2",
+            "module.down:0 DUP [1] 2",
+            "module.down:1 PUSH(0) [2] 2",
+            "module.down:4 EQ [3] 2",
+            "module.down:5 JTRUE(17) [2] 2",
+            "module.down:8 PUSH(1) [1] 2",
+            "module.down:11 SWAP [2] 2",
+            "module.down:12 SUB [2] 2",
+            "module.down:13 CALLDIRECT(0) [1] 2",
+            "enter module.down 3",
+            "module.down:0 DUP [1] 3",
+            "module.down:1 PUSH(0) [2] 3",
+            "module.down:4 EQ [3] 3",
+            "module.down:5 JTRUE(17) [2] 3",
+            "module.down:17 RET [1] 3",
+            "exit module.down 3",
+            "module.down:16 RET [1] 2",
+            "exit module.down 2",
+            "module.main:6 RET [1] 1",
+            "exit module.main 1",
+        ],
+        *events.borrow()
+    );
+
+    events.borrow_mut().clear();
+    let mut md = ModuleDef::new("module");
+    let mut builder = Builder::new("fail");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::POP);
+    md.add_function(builder.generate());
+    env.add_module(RuntimeModule::from(&md));
+    let fail = env.lookup_function("module.fail").unwrap();
+    assert!(run_loop(&fail, &mut env).is_err());
+    assert_eq!(
+        vec![
+            "enter module.fail 1",
+            "module.fail:0 POP [0] 1",
+            "error EmptyStack 1",
+        ],
+        *events.borrow()
+    );
+
+    assert!(env.remove_observer(id).is_some());
+    assert!(!env.has_observers());
+    assert!(env.remove_observer(id).is_none());
+}
+
+#[test]
+fn test_coverage_observer() {
+    let coverage = std::rc::Rc::new(std::cell::RefCell::new(crate::coverage::Coverage::default()));
+    let mut env = Environment::default();
+    env.add_observer(Box::new(crate::coverage::CoverageObserver::new(&coverage)));
+    env.add_module(RuntimeModule::from(&recursive_countdown_module(2)));
+
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    assert!(run_loop(&main, &mut env).is_ok());
+
+    let coverage = coverage.borrow();
+    assert_eq!(1, coverage.count("module.main", 0));
+    assert_eq!(1, coverage.count("module.main", 6));
+    assert_eq!(3, coverage.count("module.down", 0));
+    assert_eq!(2, coverage.count("module.down", 13));
+    assert_eq!(2, coverage.count("module.down", 16));
+    assert_eq!(1, coverage.count("module.down", 17));
+    assert_eq!(0, coverage.count("module.down", 2));
+    assert_eq!(0, coverage.count("module.other", 0));
+    assert_eq!(10, coverage.hits("module.down").unwrap().len());
+    assert_eq!(2, coverage.functions().count());
+}
+
+#[test]
+fn test_builder_lines() {
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction_at_line(InstructionDef::PUSH(0), 10);
+    block.append_instruction(InstructionDef::DUP);
+    block.append_instruction_at_line(InstructionDef::ADD, 11);
+    block.append_instruction_at_line(InstructionDef::RET, 12);
+    let fdef = builder.generate();
+    assert_eq!(
+        vec![(0, 10), (4, 11), (5, 12)],
+        fdef.lines().cloned().collect::<Vec<(usize, u32)>>()
+    );
+}
+
+#[test]
+fn test_log_levels_and_writer() {
+    use crate::{
+        log::{self, BufferWriter, LogLevel, LogSubsystem},
+        log_debug, log_error, log_info, log_subsystem,
+    };
+
+    const LOG_TEST: LogSubsystem = log_subsystem!("test_log_levels", LogLevel::Error);
+
+    assert_eq!(Ok(LogLevel::Warning), "warn".parse::<LogLevel>());
+    assert!("loud".parse::<LogLevel>().is_err());
+    assert!(log::configure("test_log_levels=loud").is_err());
+
+    // the log state is global: pin the subsystem's level so TUKUN_LOG does
+    // not leak in and put everything back even if an assert fails
+    struct Restore(Option<LogLevel>, Option<log::LogWriter>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            log::set_level("test_log_levels", self.0);
+            if let Some(writer) = self.1.take() {
+                log::set_writer(writer);
+            }
+        }
+    }
+
+    let buffer = BufferWriter::default();
+    let previous_level = log::set_level("test_log_levels", Some(LogLevel::Error));
+    let previous_writer = log::set_writer(Box::new(buffer.clone()));
+    let restore = Restore(previous_level, Some(previous_writer));
+
+    log_info!(LOG_TEST, "not shown {}", 1);
+    log_error!(LOG_TEST, "shown {}", 2);
+    assert!(log::configure("test_log_levels=info").is_ok());
+    assert_eq!(LogLevel::Info, log::level(&LOG_TEST));
+    log_info!(LOG_TEST, "shown {}", 3);
+    log_debug!(LOG_TEST, "not shown {}", 4);
+    log::set_level("test_log_levels", Some(LogLevel::Error));
+    log_info!(LOG_TEST, "not shown {}", 5);
+
+    drop(restore);
+
+    // other tests may log while the buffer is installed
+    let contents = buffer.contents();
+    let lines = contents
+        .lines()
+        .filter(|l| l.contains("test_log_levels"))
+        .collect::<Vec<&str>>();
+    assert_eq!(2, lines.len());
+    assert!(lines[0].ends_with("test_log_levels E: shown 2"));
+    assert!(lines[1].ends_with("test_log_levels I: shown 3"));
+}
+
+#[test]
+fn test_runloop_error_display() {
+    use crate::runloop::RunloopError;
+
+    let err = RunloopError {
+        cur_ptr: 6,
+        instruction: None,
+        data: RunloopErrData::InvalidOperands(
+            crate::instruction_runtime::RuntimeInstruction::ADD,
+            vec![rv_int!(1), crate::rv_str!("a")],
+        ),
+    };
+    assert_eq!(
+        "invalid operands for ADD: [Integer(1), String(\"a\")] (at offset 6)",
+        format!("{err}")
+    );
+    assert_eq!(
+        "function com.foo.bar not found",
+        format!(
+            "{}",
+            RunloopErrData::MissingFunction("com.foo.bar".to_owned())
+        )
+    );
+    assert!(RunloopErrData::MissingFunction("x".to_owned()).is_link_error());
+    assert!(!RunloopErrData::EmptyStack.is_link_error());
+
+    let mut md = ModuleDef::new("module");
+    let mut builder = Builder::new("main");
+    let mut entry = builder.append_block("entry");
+    entry.append_instruction(InstructionDef::NOP);
+    entry.append_instruction(InstructionDef::ADD);
+    entry.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&md));
+    let main = env.lookup_function("module.main").unwrap();
+    let err = run_loop(&main, &mut env).unwrap_err();
+    assert_eq!(
+        Some(crate::instruction_runtime::RuntimeInstruction::ADD),
+        err.instruction
+    );
+    assert_eq!(
+        "pop from an empty value stack in ADD (at offset 1)",
+        format!("{err}")
+    );
+
+    // jump targets are reported as byte offsets, like the offset itself
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(crate::intern_value::InternValue::Integer(0));
+    let mut builder = Builder::new("main");
+    let mut entry = builder.append_block("entry");
+    let mut exit = builder.append_block("exit");
+    entry.append_instruction(InstructionDef::PUSH(0));
+    entry.append_instruction(InstructionDef::JTRUE(exit.clone()));
+    exit.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&md));
+    let main = env.lookup_function("module.main").unwrap();
+    let err = run_loop(&main, &mut env).unwrap_err();
+    assert_eq!(
+        "invalid operands for JTRUE(6): [Integer(0)] (at offset 3)",
+        format!("{err}")
+    );
+}
+
+#[test]
+fn test_environment_call() {
+    let mut md = ModuleDef::new("module");
+    let mut builder = Builder::new("add");
+    let mut entry = builder.append_block("entry");
+    entry.append_instruction(InstructionDef::ADD);
+    entry.append_instruction(InstructionDef::RET);
+    let mut add = builder.generate();
+    add.set_arity(Some(2));
+    md.add_function(add);
+    let mut builder = Builder::new("addany");
+    let mut entry = builder.append_block("entry");
+    entry.append_instruction(InstructionDef::ADD);
+    entry.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&md));
+    env.push_value(rv_int!(100));
+
+    assert_eq!(
+        vec![rv_int!(5)],
+        env.call("module.add", &[rv_int!(2), rv_int!(3)]).unwrap()
+    );
+    assert_eq!(
+        vec![rv_int!(1), rv_int!(5)],
+        env.call("module.addany", &[rv_int!(1), rv_int!(2), rv_int!(3)])
+            .unwrap()
+    );
+
+    let err = env.call("module.add", &[rv_int!(2)]).unwrap_err();
+    assert_eq!(RunloopErrData::ArityMismatch(2, 1), err.data);
+    // the function cannot reach below its own arguments
+    let err = env.call("module.addany", &[rv_int!(2)]).unwrap_err();
+    assert_eq!(RunloopErrData::EmptyStack, err.data);
+    let err = env.call("module.nope", &[]).unwrap_err();
+    assert_eq!(
+        RunloopErrData::MissingFunction("module.nope".to_owned()),
+        err.data
+    );
+
+    assert_eq!(&[rv_int!(100)], env.stack_values());
+    assert_eq!(0, env.call_depth());
+}
+
+#[test]
+fn test_value_conversions() {
+    use crate::values::convert::{ConversionError, RuntimeRecord, RuntimeTyped};
+
+    assert_eq!(rv_int!(7), RuntimeValue::from(7u64));
+    assert_eq!(7u64, u64::try_from(rv_int!(7)).unwrap());
+    assert_eq!(-3i64, i64::try_from(RuntimeValue::from(-3i64)).unwrap());
+    assert_eq!(1.5f64, f64::try_from(RuntimeValue::from(1.5f64)).unwrap());
+    assert!(bool::try_from(RuntimeValue::from(true)).unwrap());
+    assert_eq!(
+        "hi".to_owned(),
+        String::try_from(RuntimeValue::from("hi")).unwrap()
+    );
+
+    let arr = RuntimeValue::from(vec![1u64, 2, 3]);
+    assert_eq!(
+        RuntimeType::Arr(Box::new(ArrayType::new(RuntimeType::Integer, 3))),
+        arr.get_type()
+    );
+    assert_eq!(vec![1u64, 2, 3], Vec::<u64>::try_from(arr).unwrap());
+    let empty = RuntimeValue::from(Vec::<String>::new());
+    assert!(Vec::<String>::try_from(empty).unwrap().is_empty());
+
+    // nested arrays have no static type, but read back into nested Vecs
+    let rows = RuntimeValue::Arr(crate::values::array::Array::new_inferred(&[
+        RuntimeValue::from(vec![1u64, 2]),
+        RuntimeValue::from(vec![3u64, 4]),
+    ]));
+    assert_eq!(
+        vec![vec![1u64, 2], vec![3, 4]],
+        Vec::<Vec<u64>>::try_from(rows).unwrap()
+    );
+
+    let tuple = RuntimeValue::from((1u64, "a".to_owned(), false));
+    assert_eq!(
+        RuntimeType::Record(Box::new(RecordType::new(&[
+            RuntimeType::Integer,
+            RuntimeType::String,
+            RuntimeType::Logical
+        ]))),
+        tuple.get_type()
+    );
+    assert_eq!(
+        (1u64, "a".to_owned(), false),
+        <(u64, String, bool)>::try_from(tuple).unwrap()
+    );
+
+    let err = u64::try_from(RuntimeValue::from("x")).unwrap_err();
+    assert_eq!(
+        ConversionError {
+            expected: "an integer".to_owned(),
+            found: RuntimeType::String,
+        },
+        err
+    );
+    assert!(<(u64, u64)>::try_from(RuntimeValue::from((1u64,))).is_err());
+    assert!(Vec::<u64>::try_from(RuntimeValue::from(vec![true])).is_err());
+    assert!(matches!(
+        RunloopErrData::from(err),
+        RunloopErrData::InvalidType(_)
+    ));
+
+    #[derive(Debug, PartialEq, crate::values::convert::RuntimeRecord)]
+    struct Point {
+        x: i64,
+        y: i64,
+        label: String,
+    }
+
+    assert_eq!(
+        RecordType::new(&[
+            RuntimeType::Integer,
+            RuntimeType::Integer,
+            RuntimeType::String
+        ]),
+        Point::record_type()
+    );
+    let p = Point {
+        x: -1,
+        y: 2,
+        label: "p".to_owned(),
+    };
+    let value = RuntimeValue::from(p);
+    assert_eq!(Point::runtime_type(), value.get_type());
+    assert_eq!(
+        Point {
+            x: -1,
+            y: 2,
+            label: "p".to_owned(),
+        },
+        Point::try_from(value).unwrap()
+    );
+    assert!(Point::try_from(RuntimeValue::from((1u64, 2u64))).is_err());
+}
+
+#[test]
+fn test_add_native_fn() {
+    let mut rm = RuntimeModule::new("host");
+    rm.add_native_fn("add", |a: u64, b: u64| -> u64 { a + b });
+    rm.add_native_fn("sub", |a: i64, b: i64| a - b);
+    rm.add_native_fn("greet", |name: String| format!("hello {name}"));
+    rm.add_native_fn("swap", |a: u64, b: String| (b, a));
+    rm.add_native_fn("answer", || 42u64);
+    rm.add_native_fn("drop", |_: RuntimeValue| {});
+    rm.add_native_fn("check", |x: u64| {
+        if x == 0 {
+            Err(RunloopError {
+                cur_ptr: 0,
+                instruction: None,
+                data: RunloopErrData::InvalidSlot(0),
+            })
+        } else {
+            Ok(x)
+        }
+    });
+
+    let mut env = Environment::default();
+    env.add_module(rm);
+
+    assert_eq!(
+        vec![rv_int!(5)],
+        env.call("host.add", &[rv_int!(2), rv_int!(3)]).unwrap()
+    );
+    // the last argument is the one on top of the stack
+    assert_eq!(
+        vec![RuntimeValue::from(-1i64)],
+        env.call("host.sub", &[rv_int!(2), rv_int!(3)]).unwrap()
+    );
+    assert_eq!(
+        vec![RuntimeValue::from("hello you")],
+        env.call("host.greet", &[RuntimeValue::from("you")])
+            .unwrap()
+    );
+    assert_eq!(
+        vec![RuntimeValue::from(("a".to_owned(), 1u64))],
+        env.call("host.swap", &[rv_int!(1), RuntimeValue::from("a")])
+            .unwrap()
+    );
+    assert_eq!(vec![rv_int!(42)], env.call("host.answer", &[]).unwrap());
+    assert!(env.call("host.drop", &[rv_int!(1)]).unwrap().is_empty());
+    assert_eq!(
+        vec![rv_int!(3)],
+        env.call("host.check", &[rv_int!(3)]).unwrap()
+    );
+
+    let err = env.call("host.check", &[rv_int!(0)]).unwrap_err();
+    assert_eq!(RunloopErrData::InvalidSlot(0), err.data);
+    let err = env.call("host.add", &[rv_int!(2)]).unwrap_err();
+    assert_eq!(RunloopErrData::ArityMismatch(2, 1), err.data);
+    let err = env
+        .call("host.add", &[rv_int!(2), RuntimeValue::from(true)])
+        .unwrap_err();
+    assert!(matches!(err.data, RunloopErrData::InvalidType(_)));
+}
+
+#[test]
+fn test_native_signature() {
+    use crate::native_fn::{NativeSignature, SignatureType};
+
+    struct Untyped {}
+    impl NativeCallable for Untyped {
+        fn call(&self, _: &mut Environment) -> RunloopResult {
+            Ok(())
+        }
+
+        fn name(&self) -> String {
+            String::from("untyped")
+        }
+    }
+
+    struct Sum {
+        signature: NativeSignature,
+    }
+    impl NativeCallable for Sum {
+        fn call(&self, env: &mut Environment) -> RunloopResult {
+            let arr = env.pop_value();
+            let arr = arr.as_arr().unwrap();
+            let sum = (0..arr.len())
+                .map(|i| *arr.get(i).as_integer().unwrap())
+                .sum::<u64>();
+            env.push_value(rv_int!(sum));
+            Ok(())
+        }
+
+        fn name(&self) -> String {
+            String::from("sum")
+        }
+
+        fn signature(&self) -> Option<&NativeSignature> {
+            Some(&self.signature)
+        }
+    }
+
+    let mut rm = RuntimeModule::new("host");
+    rm.add_function_native(Box::new(Untyped {}));
+    rm.add_function_native(Box::new(Sum {
+        signature: NativeSignature::new(
+            &[SignatureType::Array],
+            &[SignatureType::Of(RuntimeType::Integer)],
+        ),
+    }));
+    rm.add_native_fn("scale", |x: f64, by: u64| x * by as f64);
+    rm.add_native_fn("names", |_: RuntimeValue| vec!["a".to_owned()]);
+    assert_eq!(
+        vec!["names", "scale", "sum", "untyped"],
+        rm.function_names()
+    );
+
+    let mut env = Environment::default();
+    env.add_module(rm);
+
+    let untyped = env.lookup_function("host.untyped").unwrap();
+    assert_eq!(None, untyped.signature());
+    assert_eq!(None, untyped.arity());
+    let scale = env.lookup_function("host.scale").unwrap();
+    assert_eq!(Some(2), scale.arity());
+    assert_eq!(
+        "(type::float, type::integer) -> (type::float)",
+        scale.signature().unwrap().to_string()
+    );
+    assert_eq!(
+        "(any) -> (array)",
+        env.lookup_function("host.names")
+            .unwrap()
+            .signature()
+            .unwrap()
+            .to_string()
+    );
+
+    let arr = RuntimeValue::from(vec![1u64, 2, 3]);
+    assert_eq!(vec![rv_int!(6)], env.call("host.sum", &[arr]).unwrap());
+    let err = env.call("host.sum", &[rv_int!(1)]).unwrap_err();
+    assert_eq!(
+        "invalid type: expected array, found type::integer (at offset 0)",
+        err.to_string()
+    );
+    // the check runs before the native pops anything
+    let err = env
+        .call("host.scale", &[rv_int!(2), rv_int!(3)])
+        .unwrap_err();
+    assert_eq!(
+        "invalid type: expected type::float, found type::integer (at offset 0)",
+        err.to_string()
+    );
+    let err = env.call("host.scale", &[]).unwrap_err();
+    assert_eq!(RunloopErrData::ArityMismatch(2, 0), err.data);
+}
+
+#[test]
+fn test_host_data() {
+    #[derive(Debug, PartialEq)]
+    struct Config {
+        scale: u64,
+    }
+
+    struct Scale {}
+    impl NativeCallable for Scale {
+        fn call(&self, env: &mut Environment) -> RunloopResult {
+            let x = *env.pop_value().as_integer().unwrap();
+            let scale = env.host_data::<Config>().map_or(1, |c| c.scale);
+            env.push_value(rv_int!(x * scale));
+            *env.host_data_mut::<u64>().unwrap() += 1;
+            Ok(())
+        }
+
+        fn name(&self) -> String {
+            String::from("scale")
+        }
+    }
+
+    let mut rm = RuntimeModule::new("host");
+    rm.add_function_native(Box::new(Scale {}));
+    let mut env = Environment::default();
+    env.add_module(rm);
+
+    assert_eq!(None, env.host_data::<Config>());
+    assert_eq!(None, env.set_host_data(0u64));
+    assert_eq!(
+        vec![rv_int!(3)],
+        env.call("host.scale", &[rv_int!(3)]).unwrap()
+    );
+
+    assert_eq!(None, env.set_host_data(Config { scale: 10 }));
+    assert_eq!(
+        vec![rv_int!(30)],
+        env.call("host.scale", &[rv_int!(3)]).unwrap()
+    );
+    assert_eq!(Some(&2u64), env.host_data::<u64>());
+
+    assert_eq!(
+        Some(Config { scale: 10 }),
+        env.set_host_data(Config { scale: 2 })
+    );
+    assert_eq!(Some(Config { scale: 2 }), env.take_host_data::<Config>());
+    assert_eq!(None, env.host_data::<Config>());
+}
+
+#[test]
+fn test_module_format_header() {
+    use crate::module_definition::{MODULE_FORMAT_VERSION, MODULE_MAGIC};
+
+    let md = countdown_loop_module(3);
+    let bytes = md.to_bytes().unwrap();
+    assert!(ModuleDef::is_module(&bytes));
+    let read = ModuleDef::from_bytes(&bytes).unwrap();
+    assert_eq!(md.name(), read.name());
+    assert_eq!(md.functions().count(), read.functions().count());
+
+    let mut newer = MODULE_MAGIC.to_vec();
+    newer.extend_from_slice(&(MODULE_FORMAT_VERSION + 1).to_le_bytes());
+    newer.extend_from_slice(&bytes[MODULE_MAGIC.len() + 4..]);
+    assert_eq!(
+        Err(format!(
+            "module format version {} is not supported, expected {}",
+            MODULE_FORMAT_VERSION + 1,
+            MODULE_FORMAT_VERSION
+        )),
+        ModuleDef::from_bytes(&newer).map(|m| m.name().to_owned())
+    );
+    // files written before the header existed
+    let headerless = &bytes[MODULE_MAGIC.len() + 4..];
+    assert_eq!(
+        Err("missing module header".to_owned()),
+        ModuleDef::from_bytes(headerless).map(|m| m.name().to_owned())
+    );
+}
+
+#[test]
+fn test_calldirect_target_replaced() {
+    fn lib_module(value: u64) -> RuntimeModule {
+        let mut builder = Builder::new("value");
+        let mut block = builder.append_block("entry");
+        block.append_instruction(InstructionDef::PUSH(0));
+        block.append_instruction(InstructionDef::RET);
+        let mut f = builder.generate();
+        f.set_visibility(Visibility::Public);
+        let mut md = ModuleDef::new("lib");
+        md.add_interned_value(crate::intern_value::InternValue::Integer(value));
+        md.add_function(f);
+        RuntimeModule::from(&md)
+    }
+
+    let mut main = Builder::new("main");
+    let mut block = main.append_block("entry");
+    block.append_instruction(InstructionDef::CALLDIRECT(0));
+    block.append_instruction(InstructionDef::RET);
+    let mut md = ModuleDef::new("app");
+    md.add_call_target("lib.value");
+    md.add_function(main.generate());
+    let app = RuntimeModule::from(&md);
+
+    let mut env = Environment::default();
+    env.add_module(app.clone());
+    env.add_module(lib_module(1));
+    assert_eq!(vec![rv_int!(1)], env.call("app.main", &[]).unwrap());
+    env.add_module(lib_module(2));
+    assert_eq!(vec![rv_int!(2)], env.call("app.main", &[]).unwrap());
+
+    // the same module shared with another Environment resolves against that
+    // Environment's modules
+    let mut other = Environment::default();
+    other.add_module(app);
+    other.add_module(lib_module(3));
+    assert_eq!(vec![rv_int!(3)], other.call("app.main", &[]).unwrap());
+    assert_eq!(vec![rv_int!(2)], env.call("app.main", &[]).unwrap());
+}
+
+#[test]
+fn test_invalid_jump_target() {
+    use crate::instruction_stream::InstructionStream;
+
+    // NOP at 0, JUMP at 1 into its own operand, RET at 4
+    let mut bc = Bytecode::default();
+    bc.write_u8(u8::from(Opcode::NOP));
+    bc.write_u8(u8::from(Opcode::JUMP)).write_u16(2);
+    bc.write_u8(u8::from(Opcode::RET));
+
+    let stream = InstructionStream::from(&bc);
+    assert!(stream.is_invalid());
+    assert_eq!(1, stream.len());
+    assert_eq!(1, stream.offset(1));
+
+    let mut md = ModuleDef::new("module");
+    md.add_function(FunctionDef::new("main", bc));
+    let mut env = Environment::default();
+    env.add_module(RuntimeModule::from(&md));
+    let err = env.call("module.main", &[]).unwrap_err();
+    assert_eq!(RunloopErrData::InvalidBytecode, err.data);
+    assert_eq!(1, err.cur_ptr);
+}
+
+#[test]
+fn test_new_run_discards_suspended_run() {
+    let mut env = Environment::default();
+    env.set_instruction_budget(Some(9));
+    env.add_module(RuntimeModule::from(&countdown_loop_module(100)));
+    let main = env
+        .lookup_function("module.main")
+        .expect("main function missing");
+    assert_eq!(
+        RunloopErrData::BudgetExhausted,
+        run_loop(&main, &mut env).unwrap_err().data
+    );
+    assert!(env.is_suspended());
+
+    // a host call leaves the suspended run in place
+    env.refuel(1000);
+    let len = env.call("module.main", &[]).unwrap().len();
+    assert_eq!(1, len);
+    assert!(env.is_suspended());
+    assert_eq!(1, env.call_depth());
+
+    // starting over instead of resuming drops the old frames
+    while env.stack_len() > 0 {
+        env.pop_value();
+    }
+    env.refuel(1000);
+    assert!(run_loop(&main, &mut env).is_ok());
+    assert!(!env.is_suspended());
+    assert_eq!(0, env.call_depth());
+    assert_eq!(&[rv_int!(0)], env.stack_values());
+    assert!(resume(&mut env).is_ok());
+    assert_eq!(&[rv_int!(0)], env.stack_values());
+}
+
+#[test]
+fn test_suspend_inside_native() {
+    struct Reenter {}
+    impl NativeCallable for Reenter {
+        fn call(&self, env: &mut Environment) -> RunloopResult {
+            let main = env.lookup_function("module.main").unwrap();
+            run_loop(&main, env)
+        }
+
+        fn name(&self) -> String {
+            String::from("reenter")
+        }
+    }
+
+    let mut rm = RuntimeModule::new("host");
+    rm.add_function_native(Box::new(Reenter {}));
+    let mut env = Environment::default();
+    env.add_module(rm);
+    env.add_module(RuntimeModule::from(&countdown_loop_module(100)));
+    env.set_instruction_budget(Some(20));
+
+    let reenter = env.lookup_function("host.reenter").unwrap();
+    let err = run_loop(&reenter, &mut env).unwrap_err();
+    assert_eq!(RunloopErrData::SuspendedInNative, err.data);
+    assert!(!env.is_suspended());
+}
+
+#[test]
+fn test_newarr_unlimited_huge_type() {
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(crate::intern_value::InternValue::Integer(u64::MAX >> 8));
+
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::TYPEOF);
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::MKARRTYPE);
+    block.append_instruction(InstructionDef::NEWARR);
+    block.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    // without limits the array type is accepted, but NEWARR runs out of
+    // values instead of reserving room for all of them
+    let (_, result) = run_main_with_limits(&md, Limits::default());
+    assert_eq!(RunloopErrData::EmptyStack, result.unwrap_err().data);
+}
+
+#[test]
+fn test_newarr_empty() {
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(crate::intern_value::InternValue::Integer(0));
+
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::TYPEOF);
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::MKARRTYPE);
+    block.append_instruction(InstructionDef::NEWARR);
+    block.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let (mut env, result) = run_main_with_limits(&md, Limits::default());
+    assert!(result.is_ok());
+    assert_eq!(
+        RuntimeType::Arr(Box::new(ArrayType::new(RuntimeType::Integer, 0))),
+        env.pop_value().get_type()
+    );
+}
+
+#[test]
+fn test_newarr_mismatched_element() {
+    let mut md = ModuleDef::new("module");
+    md.add_interned_value(crate::intern_value::InternValue::Integer(2));
+    md.add_interned_value(crate::intern_value::InternValue::String("x".to_owned()));
+
+    // an array of two integers built from an integer and a string
+    let mut builder = Builder::new("main");
+    let mut block = builder.append_block("entry");
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::PUSH(1));
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::TYPEOF);
+    block.append_instruction(InstructionDef::PUSH(0));
+    block.append_instruction(InstructionDef::MKARRTYPE);
+    block.append_instruction(InstructionDef::NEWARR);
+    block.append_instruction(InstructionDef::RET);
+    md.add_function(builder.generate());
+
+    let (_, result) = run_main_with_limits(&md, Limits::default());
+    assert_eq!(
+        "invalid type: expected type::integer, found type::string",
+        result.unwrap_err().data.to_string()
+    );
+}
@@ -1,11 +1,14 @@
 use serde::{Deserialize, Serialize};
 
+use crate::module_definition::Visibility;
+
 use super::RuntimeType;
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TypeDef {
     name: String,
     target: RuntimeType,
+    visibility: Visibility,
 }
 
 impl TypeDef {
@@ -13,6 +16,7 @@ impl TypeDef {
         Self {
             name: name.to_owned(),
             target: tgt.clone(),
+            visibility: Visibility::default(),
         }
     }
 
@@ -23,4 +27,13 @@ impl TypeDef {
     pub fn target(&self) -> &RuntimeType {
         &self.target
     }
+
+    pub fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+
+    pub fn set_visibility(&mut self, v: Visibility) -> &mut Self {
+        self.visibility = v;
+        self
+    }
 }
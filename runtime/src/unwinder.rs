@@ -17,6 +17,22 @@ impl Unwinder {
         self.b.peek()
     }
 
+    pub(crate) fn top(&self) -> &Frame {
+        self.b.peek()
+    }
+
+    pub(crate) fn top_mut(&mut self) -> &mut Frame {
+        self.b.peek_mut()
+    }
+
+    pub(crate) fn split_off(&mut self, at: usize) -> Vec<Frame> {
+        self.b.values.split_off(at)
+    }
+
+    pub(crate) fn restore(&mut self, frames: Vec<Frame>) {
+        self.b.values.extend(frames);
+    }
+
     pub fn pop_frame(&mut self) -> Frame {
         self.b.pop()
     }
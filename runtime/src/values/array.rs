@@ -1,14 +1,24 @@
 use std::{cell::RefCell, rc::Rc};
 
 use crate::{
+    limits::ElementCounter,
     types::{array::ArrayType, RuntimeType},
     values::RuntimeValue,
 };
 
-#[derive(Debug)]
 struct ArrayImpl {
     at: ArrayType,
     values: Vec<RuntimeValue>,
+    counter: Option<ElementCounter>,
+}
+
+impl std::fmt::Debug for ArrayImpl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArrayImpl")
+            .field("at", &self.at)
+            .field("values", &self.values)
+            .finish()
+    }
 }
 
 #[derive(Clone)]
@@ -16,6 +26,14 @@ pub struct Array {
     a: Rc<RefCell<ArrayImpl>>,
 }
 
+impl Drop for ArrayImpl {
+    fn drop(&mut self) {
+        if let Some(counter) = &self.counter {
+            counter.sub(self.values.len());
+        }
+    }
+}
+
 impl std::fmt::Debug for Array {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.a.borrow().fmt(f)
@@ -47,7 +65,11 @@ impl Array {
         }
         let at = ArrayType::new(t, values.len());
         Self {
-            a: Rc::new(RefCell::new(ArrayImpl { at, values })),
+            a: Rc::new(RefCell::new(ArrayImpl {
+                at,
+                values,
+                counter: None,
+            })),
         }
     }
 
@@ -57,6 +79,14 @@ impl Array {
         Self::new_typed(t, v)
     }
 
+    pub(crate) fn track(&self, counter: &ElementCounter) {
+        let mut a = self.a.borrow_mut();
+        if a.counter.is_none() {
+            counter.add(a.values.len());
+            a.counter = Some(counter.clone());
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.a.borrow().values.len()
     }
@@ -31,8 +31,8 @@ pub(crate) fn compare_values(
         match (v1, v2) {
             (RuntimeValue::Integer(i1), RuntimeValue::Integer(i2)) => {
                 if signedness {
-                    let i1 = unsafe { std::mem::transmute::<u64, i64>(*i1) };
-                    let i2 = unsafe { std::mem::transmute::<u64, i64>(*i2) };
+                    let i1 = *i1 as i64;
+                    let i2 = *i2 as i64;
                     From::from(i1.cmp(&i2))
                 } else {
                     From::from(i1.cmp(i2))
@@ -0,0 +1,184 @@
+use crate::{
+    types::{record::RecordType, RuntimeType},
+    values::{array::Array, record::Record, RuntimeValue},
+};
+
+pub use runtime_derive::RuntimeRecord;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionError {
+    pub expected: String,
+    pub found: RuntimeType,
+}
+
+impl ConversionError {
+    pub fn new(expected: &str, found: &RuntimeValue) -> Self {
+        Self {
+            expected: expected.to_owned(),
+            found: found.get_type(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected {}, found {}", self.expected, self.found)
+    }
+}
+
+impl std::error::Error for ConversionError {}
+
+// lets RuntimeValue itself stand wherever a conversion is expected
+impl From<std::convert::Infallible> for ConversionError {
+    fn from(value: std::convert::Infallible) -> Self {
+        match value {}
+    }
+}
+
+// Rust types whose values all have the same runtime type; Vec is not one of
+// them, since the length of an array is part of its type. so a Vec converts
+// into a value only when its elements are RuntimeTyped, and neither nested
+// Vecs nor records with a Vec field do; build those with Array::new_inferred.
+// converting such values back into a nested Vec works
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has no static runtime type",
+    note = "an array's length is part of its type, so Vec and types holding one are not RuntimeTyped"
+)]
+pub trait RuntimeTyped {
+    fn runtime_type() -> RuntimeType;
+}
+
+// implemented by #[derive(RuntimeRecord)] for structs that map field by field
+// to a Record
+pub trait RuntimeRecord {
+    fn record_type() -> RecordType;
+}
+
+macro_rules! scalar_conversions {
+    ($t:ty, $variant:ident, $expected:literal) => {
+        impl RuntimeTyped for $t {
+            fn runtime_type() -> RuntimeType {
+                RuntimeType::$variant
+            }
+        }
+
+        impl From<$t> for RuntimeValue {
+            fn from(value: $t) -> Self {
+                RuntimeValue::$variant(value)
+            }
+        }
+
+        impl TryFrom<RuntimeValue> for $t {
+            type Error = ConversionError;
+
+            fn try_from(value: RuntimeValue) -> Result<Self, Self::Error> {
+                match value {
+                    RuntimeValue::$variant(x) => Ok(x),
+                    _ => Err(ConversionError::new($expected, &value)),
+                }
+            }
+        }
+    };
+}
+
+scalar_conversions!(u64, Integer, "an integer");
+scalar_conversions!(f64, Float, "a float");
+scalar_conversions!(bool, Logical, "a logical");
+scalar_conversions!(String, String, "a string");
+
+// signed integers are stored as their two's complement, as SLT and SGT read them
+impl RuntimeTyped for i64 {
+    fn runtime_type() -> RuntimeType {
+        RuntimeType::Integer
+    }
+}
+
+impl From<i64> for RuntimeValue {
+    fn from(value: i64) -> Self {
+        RuntimeValue::Integer(value as u64)
+    }
+}
+
+impl TryFrom<RuntimeValue> for i64 {
+    type Error = ConversionError;
+
+    fn try_from(value: RuntimeValue) -> Result<Self, Self::Error> {
+        u64::try_from(value).map(|x| x as i64)
+    }
+}
+
+impl From<&str> for RuntimeValue {
+    fn from(value: &str) -> Self {
+        RuntimeValue::String(value.to_owned())
+    }
+}
+
+// the element type comes from T, so that an empty Vec has one too
+impl<T: Into<RuntimeValue> + RuntimeTyped> From<Vec<T>> for RuntimeValue {
+    fn from(value: Vec<T>) -> Self {
+        let values = value
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<RuntimeValue>>();
+        RuntimeValue::Arr(Array::new_typed(T::runtime_type(), &values))
+    }
+}
+
+impl<T: TryFrom<RuntimeValue, Error = ConversionError>> TryFrom<RuntimeValue> for Vec<T> {
+    type Error = ConversionError;
+
+    fn try_from(value: RuntimeValue) -> Result<Self, Self::Error> {
+        match value {
+            RuntimeValue::Arr(a) => (0..a.len()).map(|i| T::try_from(a.get(i))).collect(),
+            _ => Err(ConversionError::new("an array", &value)),
+        }
+    }
+}
+
+// the fields of a record, checked against the number a conversion expects
+pub fn record_fields(
+    value: &RuntimeValue,
+    len: usize,
+) -> Result<Vec<RuntimeValue>, ConversionError> {
+    match value {
+        RuntimeValue::Record(r) if r.len() == len => Ok((0..len).map(|i| r.get(i)).collect()),
+        _ => Err(ConversionError::new(
+            &format!("a record of {len} fields"),
+            value,
+        )),
+    }
+}
+
+macro_rules! tuple_conversions {
+    ($len:literal, $($t:ident $idx:tt),+) => {
+        impl<$($t: RuntimeTyped),+> RuntimeTyped for ($($t,)+) {
+            fn runtime_type() -> RuntimeType {
+                RuntimeType::Record(Box::new(RecordType::new(&[$($t::runtime_type()),+])))
+            }
+        }
+
+        impl<$($t: Into<RuntimeValue>),+> From<($($t,)+)> for RuntimeValue {
+            fn from(value: ($($t,)+)) -> Self {
+                RuntimeValue::Record(Record::new_inferred(&[$(value.$idx.into()),+]))
+            }
+        }
+
+        impl<$($t: TryFrom<RuntimeValue, Error = ConversionError>),+> TryFrom<RuntimeValue>
+            for ($($t,)+)
+        {
+            type Error = ConversionError;
+
+            fn try_from(value: RuntimeValue) -> Result<Self, Self::Error> {
+                let fields = record_fields(&value, $len)?;
+                Ok(($($t::try_from(fields[$idx].clone())?,)+))
+            }
+        }
+    };
+}
+
+tuple_conversions!(1, A 0);
+tuple_conversions!(2, A 0, B 1);
+tuple_conversions!(3, A 0, B 1, C 2);
+tuple_conversions!(4, A 0, B 1, C 2, D 3);
+tuple_conversions!(5, A 0, B 1, C 2, D 3, E 4);
+tuple_conversions!(6, A 0, B 1, C 2, D 3, E 4, F 5);
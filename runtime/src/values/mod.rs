@@ -4,6 +4,7 @@ use crate::{intern_value::InternValue, runtime_module::RuntimeCallable, types::R
 
 pub mod array;
 pub mod comparators;
+pub mod convert;
 pub mod record;
 
 #[derive(Clone, Debug, EnumAsInner)]
@@ -34,10 +35,7 @@ impl RuntimeValue {
     }
 
     pub fn as_signed_integer(&self) -> Option<i64> {
-        unsafe {
-            self.as_unsigned_integer()
-                .map(|x| std::mem::transmute::<u64, i64>(x))
-        }
+        self.as_unsigned_integer().map(|x| x as i64)
     }
 }
 
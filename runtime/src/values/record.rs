@@ -1,14 +1,24 @@
 use std::{cell::RefCell, rc::Rc};
 
 use crate::{
+    limits::ElementCounter,
     types::{record::RecordType, RuntimeType},
     values::RuntimeValue,
 };
 
-#[derive(Debug)]
 struct RecordImpl {
     value_type: RecordType,
     values: Vec<RuntimeValue>,
+    counter: Option<ElementCounter>,
+}
+
+impl std::fmt::Debug for RecordImpl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordImpl")
+            .field("value_type", &self.value_type)
+            .field("values", &self.values)
+            .finish()
+    }
 }
 
 #[derive(Clone)]
@@ -16,6 +26,14 @@ pub struct Record {
     a: Rc<RefCell<RecordImpl>>,
 }
 
+impl Drop for RecordImpl {
+    fn drop(&mut self) {
+        if let Some(counter) = &self.counter {
+            counter.sub(self.values.len());
+        }
+    }
+}
+
 impl std::fmt::Debug for Record {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.a.borrow().fmt(f)
@@ -50,6 +68,7 @@ impl Record {
             a: Rc::new(RefCell::new(RecordImpl {
                 value_type: t,
                 values: v.to_owned(),
+                counter: None,
             })),
         }
     }
@@ -63,6 +82,14 @@ impl Record {
         Self::new_typed(rt, v)
     }
 
+    pub(crate) fn track(&self, counter: &ElementCounter) {
+        let mut a = self.a.borrow_mut();
+        if a.counter.is_none() {
+            counter.add(a.values.len());
+            a.counter = Some(counter.clone());
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.a.borrow().values.len()
     }
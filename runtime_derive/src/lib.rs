@@ -0,0 +1,87 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Index};
+
+// maps a struct to a Record whose fields follow the struct's field order; each
+// field type needs a static runtime type, which rules out Vec fields, see
+// runtime::values::convert
+#[proc_macro_derive(RuntimeRecord)]
+pub fn derive_runtime_record(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let Data::Struct(data) = &input.data else {
+        return syn::Error::new_spanned(
+            &input.ident,
+            "RuntimeRecord can only be derived for structs",
+        )
+        .to_compile_error()
+        .into();
+    };
+
+    let types = data.fields.iter().map(|f| &f.ty).collect::<Vec<_>>();
+    let len = types.len();
+    let (into_fields, from_fields) = match &data.fields {
+        Fields::Named(fields) => {
+            let idents = fields
+                .named
+                .iter()
+                .map(|f| f.ident.as_ref().unwrap())
+                .collect::<Vec<_>>();
+            (
+                quote! { #(value.#idents.into()),* },
+                quote! { Self { #(#idents: ::core::convert::TryFrom::try_from(fields.next().unwrap())?),* } },
+            )
+        }
+        Fields::Unnamed(_) => {
+            let indices = (0..len).map(Index::from).collect::<Vec<_>>();
+            let takes = (0..len).map(|_| {
+                quote! { ::core::convert::TryFrom::try_from(fields.next().unwrap())? }
+            });
+            (
+                quote! { #(value.#indices.into()),* },
+                quote! { Self ( #(#takes),* ) },
+            )
+        }
+        Fields::Unit => (quote! {}, quote! { Self }),
+    };
+
+    let expanded = quote! {
+        impl #impl_generics ::runtime::values::convert::RuntimeRecord for #name #ty_generics #where_clause {
+            fn record_type() -> ::runtime::types::record::RecordType {
+                ::runtime::types::record::RecordType::new(&[
+                    #(<#types as ::runtime::values::convert::RuntimeTyped>::runtime_type()),*
+                ])
+            }
+        }
+
+        impl #impl_generics ::runtime::values::convert::RuntimeTyped for #name #ty_generics #where_clause {
+            fn runtime_type() -> ::runtime::types::RuntimeType {
+                ::runtime::types::RuntimeType::Record(::std::boxed::Box::new(
+                    <Self as ::runtime::values::convert::RuntimeRecord>::record_type(),
+                ))
+            }
+        }
+
+        impl #impl_generics ::core::convert::From<#name #ty_generics> for ::runtime::values::RuntimeValue #where_clause {
+            fn from(value: #name #ty_generics) -> Self {
+                ::runtime::values::RuntimeValue::Record(::runtime::values::record::Record::new_typed(
+                    <#name #ty_generics as ::runtime::values::convert::RuntimeRecord>::record_type(),
+                    &[#into_fields],
+                ))
+            }
+        }
+
+        impl #impl_generics ::core::convert::TryFrom<::runtime::values::RuntimeValue> for #name #ty_generics #where_clause {
+            type Error = ::runtime::values::convert::ConversionError;
+
+            fn try_from(value: ::runtime::values::RuntimeValue) -> ::core::result::Result<Self, Self::Error> {
+                #[allow(unused_mut, unused_variables)]
+                let mut fields = ::runtime::values::convert::record_fields(&value, #len)?.into_iter();
+                ::core::result::Result::Ok(#from_fields)
+            }
+        }
+    };
+    expanded.into()
+}